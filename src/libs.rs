@@ -1,6 +1,21 @@
 pub mod handle_keys;
 pub mod make_map;
+pub mod generators;
+pub mod vaults;
+pub mod hazards;
+pub mod traps;
+pub mod secrets;
+pub mod themes;
+pub mod validate;
 pub mod render;
 pub mod ai;
 pub mod menu;
-pub mod itens_effects;
\ No newline at end of file
+pub mod itens_effects;
+pub mod game_state;
+pub mod replay;
+pub mod content;
+pub mod save;
+pub mod keymap;
+pub mod canvas;
+pub mod backend;
+pub mod export;
\ No newline at end of file
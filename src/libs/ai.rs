@@ -1,16 +1,16 @@
 
-use std::cmp;
 use rand::Rng;
 
 use tcod::colors::*;
+use tcod::map::Map as FovMap;
 
 use crate::predefs::structs::*;
 use crate::predefs::constants::*;
 use crate::libs::make_map::move_by;
-use crate::libs::menu::*;
+use crate::libs::generators::NEIGHBOURS;
 
 
-pub fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects: &mut [Object]) {
+pub fn move_towards(id: EntityId, target_x: i32, target_y: i32, fov: &FovMap, game: &mut Game, objects: &mut Objects) {
     // vector from this object to the target, and distance
     let dx = target_x - objects[id].x;
     let dy = target_y - objects[id].y;
@@ -20,35 +20,60 @@ pub fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects:
     // convert to integer so the movement is restricted to the map grid
     let dx = (dx as f32 / distance).round() as i32;
     let dy = (dy as f32 / distance).round() as i32;
-    move_by(id, dx, dy, map, objects);
+    let (dx, dy) = avoid_hazards(id, (dx, dy), (target_x, target_y), &game.map, objects);
+    move_by(id, dx, dy, fov, game, objects);
 }
 
-pub fn ai_take_turn(monster_id: usize, tcod: &Tcod, game: &mut Game, objects: &mut [Object]) {
+/// the step to take instead, when `step` leads into a hazard the monster isn't immune
+/// to: any other step that gets closer will do, clear ground first and shallow water
+/// after that. if there is none, it stays where it is
+fn avoid_hazards(id: EntityId, step: (i32, i32), target: (i32, i32), map: &Map, objects: &Objects) -> (i32, i32) {
+    let object = &objects[id];
+    let distance = |(x, y): (i32, i32)| (target.0 - x).pow(2) + (target.1 - y).pow(2);
+    let mut closer: Vec<(i32, i32)> = NEIGHBOURS
+        .iter()
+        .cloned()
+        .filter(|&(dx, dy)| distance((object.x + dx, object.y + dy)) < distance(object.pos()))
+        .collect();
+    closer.sort_by_key(|&(dx, dy)| distance((object.x + dx, object.y + dy)));
+    let steps: Vec<(i32, i32)> = std::iter::once(step).chain(closer).collect();
+
+    let hazard = |&&(dx, dy): &&(i32, i32)| object.hazard_in(&map[(object.x + dx) as usize][(object.y + dy) as usize]);
+    steps
+        .iter()
+        .find(|step| hazard(step).is_none())
+        .or_else(|| steps.iter().find(|step| !hazard(step).is_some_and(Hazard::harmful)))
+        .cloned()
+        .unwrap_or((0, 0))
+}
+
+pub fn ai_take_turn(monster_id: EntityId, fov: &FovMap, game: &mut Game, objects: &mut Objects) {
     use Ai::*;
     if let Some(ai) = objects[monster_id].ai.take() {
         let new_ai = match ai {
-            Basic => ai_basic(monster_id, tcod, game, objects),
+            Basic => ai_basic(monster_id, fov, game, objects),
             Confused {
                 previous_ai,
                 num_turns,
-            } => ai_confused(monster_id, tcod, game, objects, previous_ai, num_turns),
+            } => ai_confused(monster_id, fov, game, objects, previous_ai, num_turns),
+            Alerted { previous_ai, x, y } => ai_alerted(monster_id, fov, game, objects, previous_ai, (x, y)),
         };
         objects[monster_id].ai = Some(new_ai);
     }
 }
 
-fn ai_basic(monster_id: usize, tcod: &Tcod, game: &mut Game, objects: &mut [Object]) -> Ai {
+fn ai_basic(monster_id: EntityId, fov: &FovMap, game: &mut Game, objects: &mut Objects) -> Ai {
 
     // a// a basic monster takes its turn. If you can see it, it can see you
     let (monster_x, monster_y) = objects[monster_id].pos();
-    if tcod.fov.is_in_fov(monster_x, monster_y) {
+    if fov.is_in_fov(monster_x, monster_y) {
         if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
             // move towards player if far away
             let (player_x, player_y) = objects[PLAYER].pos();
-            move_towards(monster_id, player_x, player_y, &game.map, objects);
-        } else if objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
+            move_towards(monster_id, player_x, player_y, fov, game, objects);
+        } else if objects[PLAYER].fighter.is_some_and(|f| f.hp > 0) {
             // close enough, attack! (if the player is still alive.)
-            let (monster, player) = mut_two(monster_id, PLAYER, objects);
+            let (monster, player) = objects.pair_mut(monster_id, PLAYER);
             monster.attack(player, game);
         }
     }
@@ -56,10 +81,10 @@ fn ai_basic(monster_id: usize, tcod: &Tcod, game: &mut Game, objects: &mut [Obje
 }
 
 fn ai_confused(
-    monster_id: usize,
-    _tcod: &Tcod,
+    monster_id: EntityId,
+    fov: &FovMap,
     game: &mut Game,
-    objects: &mut [Object],
+    objects: &mut Objects,
     previous_ai: Box<Ai>,
     num_turns: i32,
 ) -> Ai {
@@ -68,13 +93,14 @@ fn ai_confused(
         // move in a random direction, and decrease the number of turns confused
         move_by(
             monster_id,
-            rand::thread_rng().gen_range(-1, 2),
-            rand::thread_rng().gen_range(-1, 2),
-            &game.map,
+            game.rng.gen_range(-1, 2),
+            game.rng.gen_range(-1, 2),
+            fov,
+            game,
             objects,
         );
         Ai::Confused {
-            previous_ai: previous_ai,
+            previous_ai,
             num_turns: num_turns - 1,
         }
     } else {
@@ -86,59 +112,69 @@ fn ai_confused(
         *previous_ai
     }
 }
-pub fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
-    assert!(first_index != second_index);
-    let split_at_index = cmp::max(first_index, second_index);
-    let (first_slice, second_slice) = items.split_at_mut(split_at_index);
-    if first_index < second_index {
-        (&mut first_slice[first_index], &mut second_slice[0])
+/// a monster woken up by an alarm goes to see what is going on, and is back to its
+/// usual self once it gets there or sees the player
+fn ai_alerted(
+    monster_id: EntityId,
+    fov: &FovMap,
+    game: &mut Game,
+    objects: &mut Objects,
+    previous_ai: Box<Ai>,
+    (x, y): (i32, i32),
+) -> Ai {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    if fov.is_in_fov(monster_x, monster_y) || (monster_x, monster_y) == (x, y) {
+        *previous_ai
     } else {
-        (&mut second_slice[0], &mut first_slice[second_index])
+        move_towards(monster_id, x, y, fov, game, objects);
+        Ai::Alerted { previous_ai, x, y }
     }
 }
 
-pub fn level_up(tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
+/// replace the monster's AI with a "confused" one; after some turns it will restore the old AI
+pub fn confuse(monster_id: EntityId, turns: i32, objects: &mut Objects) {
+    let old_ai = objects[monster_id].ai.take().unwrap_or(Ai::Basic);
+    objects[monster_id].ai = Some(Ai::Confused {
+        previous_ai: Box::new(old_ai),
+        num_turns: turns,
+    });
+}
+
+/// see if the player's experience is enough to level-up
+pub fn can_level_up(player: &Object) -> bool {
+    player.fighter.as_ref().map_or(0, |f| f.xp) >= level_up_xp(player)
+}
+
+pub fn level_up_xp(player: &Object) -> i32 {
+    LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR
+}
+
+pub fn level_up(stat: Stat, game: &mut Game, objects: &mut Objects) {
     let player = &mut objects[PLAYER];
-    let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
-    // see if the player's experience is enough to level-up
-    if player.fighter.as_ref().map_or(0, |f| f.xp) >= level_up_xp {
-        // it is! level up
-        player.level += 1;
-        game.messages.add(
-            format!(
-                "Your battle skills grow stronger! You reached level {}!",
-                player.level
-            ),
-            YELLOW,
-        );
-        let fighter = player.fighter.as_mut().unwrap();
-        let mut choice = None;
-        while choice.is_none() {
-            // keep asking until a choice is made
-            choice = menu(
-                "Level up! Choose a stat to raise:\n",
-                &[
-                    format!("Constitution (+20 HP, from {})", fighter.base_max_hp),
-                    format!("Strength (+1 attack, from {})", fighter.base_power),
-                    format!("Agility (+1 defense, from {})", fighter.base_defense),
-                ],
-                LEVEL_SCREEN_WIDTH,
-                &mut tcod.root,
-            );
+    if !can_level_up(player) {
+        return;
+    }
+    let level_up_xp = level_up_xp(player);
+    player.level += 1;
+    game.messages.add(
+        format!(
+            "Your battle skills grow stronger! You reached level {}!",
+            player.level
+        ),
+        YELLOW,
+    );
+    let fighter = player.fighter.as_mut().unwrap();
+    fighter.xp -= level_up_xp;
+    match stat {
+        Stat::Constitution => {
+            fighter.base_max_hp += 20;
+            fighter.hp += 20;
         }
-        fighter.xp -= level_up_xp;
-        match choice.unwrap() {
-            0 => {
-                fighter.base_max_hp += 20;
-                fighter.hp += 20;
-            }
-            1 => {
-                fighter.base_power += 1;
-            }
-            2 => {
-                fighter.base_defense += 1;
-            }
-            _ => unreachable!(),
+        Stat::Strength => {
+            fighter.base_power += 1;
+        }
+        Stat::Agility => {
+            fighter.base_defense += 1;
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,67 @@
+use crate::predefs::structs::*;
+
+pub mod window;
+pub mod snapshot;
+#[cfg(unix)]
+pub mod terminal;
+
+/// something that can show the game's screen and read the player's input
+pub trait Backend {
+    /// show the whole screen; this also keeps the frame rate in check
+    fn present(&mut self, screen: &Canvas);
+
+    /// the next key press or mouse event, without waiting for one
+    fn poll_input(&mut self) -> Option<Input>;
+
+    /// block until a key is pressed; once closed, return Escape straight away
+    fn wait_for_key(&mut self) -> KeyPress;
+
+    /// the window was closed, or the terminal went away
+    fn closed(&self) -> bool;
+
+    fn toggle_fullscreen(&mut self) {}
+
+    /// draw a picture over the whole screen, if the backend can show pictures
+    fn draw_image(&mut self, _path: &str, _screen: &mut Canvas) {}
+}
+
+impl Ui {
+    pub fn new(backend: Box<dyn Backend>, keymap: Keymap) -> Self {
+        use crate::predefs::constants::*;
+        Ui {
+            backend,
+            root: Canvas::new(SCREEN_WIDTH, SCREEN_HEIGHT),
+            con: Canvas::new(VIEW_WIDTH, VIEW_HEIGHT),
+            panel: Canvas::new(SCREEN_WIDTH, PANEL_HEIGHT),
+            key: Default::default(),
+            mouse: Default::default(),
+            keymap,
+        }
+    }
+
+    /// read the latest input: key presses and clicks only last one frame, the mouse
+    /// stays where it is
+    pub fn poll(&mut self) {
+        self.key = Default::default();
+        self.mouse.left_click = false;
+        self.mouse.right_click = false;
+        match self.backend.poll_input() {
+            Some(Input::Mouse(mouse)) => self.mouse = mouse,
+            Some(Input::Key(key)) => self.key = key,
+            None => {}
+        }
+    }
+
+    /// show the root canvas
+    pub fn flush(&mut self) {
+        self.backend.present(&self.root);
+    }
+
+    pub fn wait_for_key(&mut self) -> KeyPress {
+        self.backend.wait_for_key()
+    }
+
+    pub fn closed(&self) -> bool {
+        self.backend.closed()
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::VecDeque;
+use std::io::Write;
+
+use tcod::input::KeyCode;
+
+use crate::predefs::structs::*;
+use crate::libs::backend::Backend;
+
+/// shows nothing: every frame is written out as text instead (the characters, then
+/// the background colours), and the input comes from a script. What the game draws
+/// can then be checked, or compared between two versions, without any display.
+pub struct SnapshotBackend {
+    out: Box<dyn Write>,
+    inputs: VecDeque<Input>,
+}
+
+impl SnapshotBackend {
+    pub fn new(out: Box<dyn Write>, inputs: Vec<Input>) -> Self {
+        SnapshotBackend {
+            out,
+            inputs: inputs.into(),
+        }
+    }
+}
+
+impl Backend for SnapshotBackend {
+    fn present(&mut self, screen: &Canvas) {
+        let _ = writeln!(self.out, "{}\n\n{}\n", screen.text(), screen.background_text());
+    }
+
+    fn poll_input(&mut self) -> Option<Input> {
+        self.inputs.pop_front()
+    }
+
+    /// once the script runs out, every question is answered with Escape
+    fn wait_for_key(&mut self) -> KeyPress {
+        while let Some(input) = self.inputs.pop_front() {
+            if let Input::Key(key) = input {
+                return key;
+            }
+        }
+        KeyPress {
+            code: KeyCode::Escape,
+            ..Default::default()
+        }
+    }
+
+    fn closed(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::io;
+    use std::rc::Rc;
+
+    use tcod::colors::*;
+
+    use super::*;
+    use crate::predefs::constants::*;
+    use crate::libs::content::load_content;
+    use crate::libs::menu::new_game;
+    use crate::libs::render::render_all;
+
+    /// what the backend wrote, kept to look at after the frame
+    #[derive(Clone, Default)]
+    struct Written(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Written {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// one frame: the characters and the background letters of each row, and the colour
+    /// of each letter
+    struct Frame {
+        text: Vec<String>,
+        background: Vec<String>,
+        colors: HashMap<char, String>,
+    }
+
+    impl Frame {
+        fn char_at(&self, x: i32, y: i32) -> char {
+            self.text[y as usize].chars().nth(x as usize).unwrap_or(' ')
+        }
+
+        /// the background as `#rrggbb`, empty for black
+        fn background_at(&self, x: i32, y: i32) -> String {
+            let letter = self.background[y as usize].chars().nth(x as usize).unwrap_or(' ');
+            self.colors.get(&letter).cloned().unwrap_or_default()
+        }
+
+        /// a stretch of a row, without the spaces at the end
+        fn text_at(&self, x: i32, y: i32, width: i32) -> String {
+            let row: String = self.text[y as usize].chars().skip(x as usize).take(width as usize).collect();
+            row.trim_end().to_string()
+        }
+    }
+
+    fn hex(color: Color) -> String {
+        format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+    }
+
+    fn render(state: &GameState) -> Frame {
+        let written = Written::default();
+        let backend = SnapshotBackend::new(Box::new(written.clone()), vec![]);
+        let mut ui = Ui::new(Box::new(backend), Keymap { bindings: vec![] });
+        render_all(&mut ui, state);
+        ui.flush();
+
+        let output = String::from_utf8(written.0.borrow().clone()).unwrap();
+        let mut lines = output.lines().map(String::from);
+        let text: Vec<String> = lines.by_ref().take(SCREEN_HEIGHT as usize).collect();
+        assert_eq!(lines.next().as_deref(), Some(""));
+        let background: Vec<String> = lines.by_ref().take(SCREEN_HEIGHT as usize).collect();
+        let colors = lines
+            .filter_map(|line| {
+                let (letter, color) = line.split_once(" = ")?;
+                Some((letter.chars().next()?, color.to_string()))
+            })
+            .collect();
+        Frame { text, background, colors }
+    }
+
+    /// a lit room with the player in it, and next to it, behind a thick wall, a room
+    /// explored earlier with the stairs, a potion and an orc in it
+    fn two_rooms() -> GameState {
+        let content = load_content().expect("the game data loads");
+        let mut state = new_game(42, &content);
+        let GameState { game, objects, .. } = &mut state;
+        objects.retain(|id, _| id == PLAYER);
+        game.messages = Messages::new();
+        for column in game.map.iter_mut() {
+            for tile in column.iter_mut() {
+                *tile = Tile::wall();
+            }
+        }
+        for x in 5..=15 {
+            for y in 5..=15 {
+                game.map[x][y] = Tile::empty();
+            }
+        }
+        for x in 20..=25 {
+            for y in 5..=15 {
+                game.map[x][y] = Tile::empty();
+                game.map[x][y].explored = true;
+            }
+        }
+        objects[PLAYER].set_pos(10, 10);
+        let mut stairs = Object::new(22, 10, '>', "stairs down", WHITE, false);
+        stairs.always_visible = true;
+        objects.insert(stairs);
+        objects.insert(content.item("healing_potion").unwrap().create(23, 10));
+        objects.insert(content.monster("orc").unwrap().create(24, 10));
+        state.recompute_fov();
+        state
+    }
+
+    #[test]
+    fn seen_and_remembered_tiles() {
+        let state = two_rooms();
+        let palette = state.content.palette(state.game.theme.as_deref());
+        let frame = render(&state);
+        assert_eq!(frame.char_at(10, 10), '@');
+        // in sight
+        assert_eq!(frame.background_at(12, 10), hex(palette.light_ground));
+        assert_eq!(frame.background_at(4, 10), hex(palette.light_wall));
+        assert_eq!(frame.background_at(16, 10), hex(palette.light_wall));
+        // explored, but out of sight
+        assert_eq!(frame.background_at(21, 10), hex(palette.dark_ground));
+        // never seen
+        assert_eq!(frame.background_at(18, 10), "");
+        assert_eq!(frame.background_at(40, 30), "");
+    }
+
+    #[test]
+    fn only_always_visible_objects_show_out_of_sight() {
+        let frame = render(&two_rooms());
+        assert_eq!(frame.char_at(22, 10), '>');
+        assert_eq!(frame.char_at(23, 10), ' ');
+        assert_eq!(frame.char_at(24, 10), ' ');
+    }
+
+    #[test]
+    fn hp_bar() {
+        let mut state = two_rooms();
+        state.objects[PLAYER].fighter.as_mut().unwrap().hp = 60;
+        let frame = render(&state);
+        let y = PANEL_Y + 1;
+        assert_eq!(frame.text_at(1, y, BAR_WIDTH).trim_start(), "HP: 60/100");
+        // 60% of the bar is full, the rest is empty
+        let full = BAR_WIDTH * 60 / 100;
+        assert!((1..1 + full).all(|x| frame.background_at(x, y) == hex(LIGHT_RED)));
+        assert!((1 + full..1 + BAR_WIDTH).all(|x| frame.background_at(x, y) == hex(DARKER_RED)));
+        assert_eq!(frame.background_at(1 + BAR_WIDTH, y), "");
+        assert_eq!(frame.text_at(1, PANEL_Y + 3, BAR_WIDTH), "Dungeon level: 1");
+    }
+
+    #[test]
+    fn messages_wrap_and_old_ones_scroll_away() {
+        let mut state = two_rooms();
+        for n in 1..=6 {
+            state.game.messages.add(format!("Message number {}.", n), WHITE);
+        }
+        let long = "The orc swings its rusty axe in a wide arc, and you only just step out of \
+                    the way before it bites into the wall behind you.";
+        state.game.messages.add(long, RED);
+        let frame = render(&state);
+
+        // the newest message is at the bottom, wrapped on as many lines as it needs
+        let lines: Vec<String> = (0..MSG_HEIGHT as i32)
+            .map(|y| frame.text_at(MSG_X, PANEL_Y + y, MSG_WIDTH))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                // the oldest ones no longer fit
+                "Message number 4.",
+                "Message number 5.",
+                "Message number 6.",
+                "The orc swings its rusty axe in a wide arc, and you only",
+                "just step out of the way before it bites into the wall",
+                "behind you.",
+            ]
+        );
+        assert!(lines.iter().all(|line| line.chars().count() <= MSG_WIDTH as usize));
+    }
+}
@@ -0,0 +1,474 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::mem;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tcod::colors::Color;
+use tcod::input::KeyCode;
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::backend::Backend;
+
+// alternate screen, hidden cursor, every mouse event in SGR form, application keypad
+const ENTER_SCREEN: &str = "\x1b[?1049h\x1b[?25l\x1b[?1003h\x1b[?1006h\x1b=\x1b[2J";
+const LEAVE_SCREEN: &str = "\x1b[?1006l\x1b[?1003l\x1b>\x1b[0m\x1b[?25h\x1b[?1049l";
+
+/// plays in the terminal it was started from, with ANSI colours, so no window is
+/// needed (over SSH for instance). The terminal needs 24-bit colour and mouse support.
+pub struct TerminalBackend {
+    original_mode: libc::termios,
+    /// what is on the terminal already, so only the changes are sent
+    shown: Option<Canvas>,
+    last_frame: Instant,
+    /// bytes read from the terminal that are not a whole key yet
+    pending: Vec<u8>,
+    closed: bool,
+}
+
+impl TerminalBackend {
+    pub fn new() -> Result<Self, String> {
+        let (columns, rows) = terminal_size().ok_or("the game is not running in a terminal")?;
+        if columns < SCREEN_WIDTH || rows < SCREEN_HEIGHT {
+            return Err(format!(
+                "the terminal must be at least {}x{} characters, it is {}x{}",
+                SCREEN_WIDTH, SCREEN_HEIGHT, columns, rows
+            ));
+        }
+        // raw mode: every key comes in as soon as it is pressed, and nothing is echoed
+        let original_mode = unsafe {
+            let mut mode: libc::termios = mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut mode) != 0 {
+                return Err(io::Error::last_os_error().to_string());
+            }
+            let mut raw = mode;
+            libc::cfmakeraw(&mut raw);
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw);
+            mode
+        };
+        // when the terminal hangs up (an SSH connection dropping, say) the game reads the
+        // end of its input, and gets to save, instead of being killed on the spot
+        unsafe {
+            libc::signal(libc::SIGHUP, libc::SIG_IGN);
+        }
+        write_out(ENTER_SCREEN);
+        Ok(TerminalBackend {
+            original_mode,
+            shown: None,
+            last_frame: Instant::now(),
+            pending: vec![],
+            closed: false,
+        })
+    }
+
+    fn restore(&mut self) {
+        write_out(LEAVE_SCREEN);
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original_mode);
+        }
+    }
+
+    /// read whatever the terminal sent, waiting up to `timeout` milliseconds (-1: forever)
+    fn read_input(&mut self, timeout: i32) {
+        let mut poll = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut poll, 1, timeout) } <= 0 {
+            return;
+        }
+        let mut buffer = [0u8; 256];
+        let read = unsafe { libc::read(libc::STDIN_FILENO, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+        if read <= 0 {
+            // the terminal hung up
+            self.closed = true;
+        } else {
+            self.pending.extend_from_slice(&buffer[..read as usize]);
+        }
+    }
+
+    fn next_input(&mut self, timeout: i32) -> Option<Input> {
+        loop {
+            if self.pending.is_empty() {
+                self.read_input(timeout);
+                if self.pending.is_empty() {
+                    return None;
+                }
+            }
+            match parse_input(&self.pending) {
+                Parsed::Input(input, length) => {
+                    self.pending.drain(..length);
+                    return Some(input);
+                }
+                Parsed::Skip(length) => {
+                    self.pending.drain(..length);
+                }
+                Parsed::Incomplete => {
+                    // the rest of an escape sequence comes right away; if it doesn't,
+                    // Escape itself was pressed
+                    let before = self.pending.len();
+                    self.read_input(ESCAPE_DELAY);
+                    if self.pending.len() == before {
+                        let alone = self.pending.drain(..).count() == 1;
+                        return alone.then(|| Input::Key(key(KeyCode::Escape, '\0')));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// milliseconds to wait for the rest of an escape sequence
+const ESCAPE_DELAY: i32 = 25;
+
+impl Backend for TerminalBackend {
+    fn present(&mut self, screen: &Canvas) {
+        let mut out = String::new();
+        let mut cursor = None;
+        let mut colors = None;
+        for y in 0..screen.height {
+            for x in 0..screen.width {
+                let cell = screen.cells[(y * screen.width + x) as usize];
+                let unchanged = self
+                    .shown
+                    .as_ref()
+                    .is_some_and(|shown| shown.cell(x, y) == Some(&cell));
+                if unchanged {
+                    continue;
+                }
+                if cursor != Some((x, y)) {
+                    let _ = write!(out, "\x1b[{};{}H", y + 1, x + 1);
+                }
+                if colors != Some((cell.fg, cell.bg)) {
+                    let _ = write!(out, "\x1b[38;2;{}m\x1b[48;2;{}m", rgb(cell.fg), rgb(cell.bg));
+                    colors = Some((cell.fg, cell.bg));
+                }
+                // control characters would mess up the terminal
+                out.push(if cell.ch < ' ' { ' ' } else { cell.ch });
+                cursor = Some((x + 1, y));
+            }
+        }
+        write_out(&out);
+        self.shown = Some(screen.clone());
+
+        // keep to the same frame rate as the window
+        let frame = Duration::from_millis(1000 / LIMIT_FPS as u64);
+        if let Some(rest) = frame.checked_sub(self.last_frame.elapsed()) {
+            thread::sleep(rest);
+        }
+        self.last_frame = Instant::now();
+    }
+
+    fn poll_input(&mut self) -> Option<Input> {
+        self.next_input(0)
+    }
+
+    /// once the terminal is gone, every question is answered with Escape, so the game
+    /// backs out of its menus and saves on the way out like when the player leaves
+    fn wait_for_key(&mut self) -> KeyPress {
+        loop {
+            if self.closed {
+                return key(KeyCode::Escape, '\0');
+            }
+            if let Some(Input::Key(key)) = self.next_input(-1) {
+                return key;
+            }
+        }
+    }
+
+    fn closed(&self) -> bool {
+        self.closed
+    }
+}
+
+impl Drop for TerminalBackend {
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
+
+fn write_out(text: &str) {
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(text.as_bytes());
+    let _ = stdout.flush();
+}
+
+fn rgb(color: Color) -> String {
+    format!("{};{};{}", color.r, color.g, color.b)
+}
+
+fn terminal_size() -> Option<(i32, i32)> {
+    unsafe {
+        let mut size: libc::winsize = mem::zeroed();
+        if libc::isatty(libc::STDIN_FILENO) == 0 || libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) != 0 {
+            return None;
+        }
+        Some((size.ws_col as i32, size.ws_row as i32))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Parsed {
+    Input(Input, usize),
+    /// bytes that mean nothing to the game
+    Skip(usize),
+    Incomplete,
+}
+
+fn key(code: KeyCode, printable: char) -> KeyPress {
+    KeyPress {
+        code,
+        printable,
+        ..Default::default()
+    }
+}
+
+/// turn the first key or mouse event of what the terminal sent into an `Input`
+fn parse_input(bytes: &[u8]) -> Parsed {
+    match bytes {
+        [0x1b, b'[', b'<', rest @ ..] => parse_mouse(rest, 3),
+        [0x1b, b'[', rest @ ..] => parse_csi(rest),
+        [0x1b, b'O', code, ..] => match ss3_key(*code) {
+            Some(code) => Parsed::Input(Input::Key(key(code, '\0')), 3),
+            None => Parsed::Skip(3),
+        },
+        [0x1b, b'O'] | [0x1b] => Parsed::Incomplete,
+        // Escape followed by a key is that key with Alt held
+        [0x1b, rest @ ..] => match parse_input(rest) {
+            Parsed::Input(Input::Key(mut key), length) => {
+                key.alt = true;
+                Parsed::Input(Input::Key(key), length + 1)
+            }
+            Parsed::Input(_, length) | Parsed::Skip(length) => Parsed::Skip(length + 1),
+            Parsed::Incomplete => Parsed::Incomplete,
+        },
+        [b'\r', ..] | [b'\n', ..] => Parsed::Input(Input::Key(key(KeyCode::Enter, '\r')), 1),
+        [b'\t', ..] => Parsed::Input(Input::Key(key(KeyCode::Tab, '\t')), 1),
+        [0x7f, ..] | [0x08, ..] => Parsed::Input(Input::Key(key(KeyCode::Backspace, '\0')), 1),
+        [b' ', ..] => Parsed::Input(Input::Key(key(KeyCode::Spacebar, ' ')), 1),
+        // Ctrl+letter
+        [byte @ 0x01..=0x1a, ..] => {
+            let mut key = key(KeyCode::Char, (b'a' + byte - 1) as char);
+            key.ctrl = true;
+            Parsed::Input(Input::Key(key), 1)
+        }
+        [first, ..] => {
+            let length = match first {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => 1,
+            };
+            if bytes.len() < length {
+                return Parsed::Incomplete;
+            }
+            match std::str::from_utf8(&bytes[..length]).ok().and_then(|text| text.chars().next()) {
+                Some(ch) if !ch.is_control() => Parsed::Input(Input::Key(key(KeyCode::Text, ch)), length),
+                _ => Parsed::Skip(length),
+            }
+        }
+        [] => Parsed::Incomplete,
+    }
+}
+
+/// `ESC [ params final`: arrows, Home, End, PageUp, function keys... with modifiers
+fn parse_csi(rest: &[u8]) -> Parsed {
+    let end = match rest.iter().position(|byte| (0x40..=0x7e).contains(byte)) {
+        Some(end) => end,
+        None => return Parsed::Incomplete,
+    };
+    let length = end + 3;
+    let params: Vec<u32> = std::str::from_utf8(&rest[..end])
+        .unwrap_or("")
+        .split(';')
+        .map(|param| param.parse().unwrap_or(0))
+        .collect();
+    let code = match (rest[end], params[0]) {
+        (b'A', _) => KeyCode::Up,
+        (b'B', _) => KeyCode::Down,
+        (b'C', _) => KeyCode::Right,
+        (b'D', _) => KeyCode::Left,
+        (b'H', _) | (b'~', 1) | (b'~', 7) => KeyCode::Home,
+        (b'F', _) | (b'~', 4) | (b'~', 8) => KeyCode::End,
+        (b'E', _) => KeyCode::NumPad5,
+        (b'~', 2) => KeyCode::Insert,
+        (b'~', 3) => KeyCode::Delete,
+        (b'~', 5) => KeyCode::PageUp,
+        (b'~', 6) => KeyCode::PageDown,
+        (b'~', 11) => KeyCode::F1,
+        (b'~', 12) => KeyCode::F2,
+        (b'~', 13) => KeyCode::F3,
+        (b'~', 14) => KeyCode::F4,
+        (b'~', 15) => KeyCode::F5,
+        (b'~', 17) => KeyCode::F6,
+        (b'~', 18) => KeyCode::F7,
+        (b'~', 19) => KeyCode::F8,
+        (b'~', 20) => KeyCode::F9,
+        (b'~', 21) => KeyCode::F10,
+        (b'~', 23) => KeyCode::F11,
+        (b'~', 24) => KeyCode::F12,
+        _ => return Parsed::Skip(length),
+    };
+    // the second parameter is 1 plus a bit mask of the modifiers: 2 for Alt, 4 for Ctrl
+    let modifiers = params.get(1).map_or(0, |modifiers| modifiers.saturating_sub(1));
+    let mut key = key(code, '\0');
+    key.alt = modifiers & 2 != 0;
+    key.ctrl = modifiers & 4 != 0;
+    Parsed::Input(Input::Key(key), length)
+}
+
+/// `ESC O code`: arrows, Home and End in some terminals, F1-F4 and the numeric keypad
+fn ss3_key(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+    let numpad = [NumPad0, NumPad1, NumPad2, NumPad3, NumPad4, NumPad5, NumPad6, NumPad7, NumPad8, NumPad9];
+    Some(match code {
+        b'A' => Up,
+        b'B' => Down,
+        b'C' => Right,
+        b'D' => Left,
+        b'H' => Home,
+        b'F' => End,
+        b'P' => F1,
+        b'Q' => F2,
+        b'R' => F3,
+        b'S' => F4,
+        b'M' => NumPadEnter,
+        b'p'..=b'y' => numpad[(code - b'p') as usize],
+        _ => return None,
+    })
+}
+
+/// `ESC [ < button ; column ; row M` (pressed or moved) or `m` (released)
+fn parse_mouse(rest: &[u8], prefix: usize) -> Parsed {
+    let end = match rest.iter().position(|&byte| byte == b'M' || byte == b'm') {
+        Some(end) => end,
+        None if rest.iter().all(|byte| byte.is_ascii_digit() || *byte == b';') => return Parsed::Incomplete,
+        None => return Parsed::Skip(prefix),
+    };
+    let length = prefix + end + 1;
+    let params: Vec<i32> = std::str::from_utf8(&rest[..end])
+        .unwrap_or("")
+        .split(';')
+        .filter_map(|param| param.parse().ok())
+        .collect();
+    let (button, column, row) = match params[..] {
+        [button, column, row] => (button, column, row),
+        _ => return Parsed::Skip(length),
+    };
+    // 32 is set when the mouse moved, 64 for the wheel
+    let click = rest[end] == b'M' && button & (32 | 64) == 0;
+    Parsed::Input(
+        Input::Mouse(MouseState {
+            x: column - 1,
+            y: row - 1,
+            left_click: click && button & 3 == 0,
+            right_click: click && button & 3 == 2,
+        }),
+        length,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_with(code: KeyCode, printable: char, ctrl: bool, alt: bool) -> Input {
+        let mut key = key(code, printable);
+        key.ctrl = ctrl;
+        key.alt = alt;
+        Input::Key(key)
+    }
+
+    fn mouse(x: i32, y: i32, left_click: bool, right_click: bool) -> Input {
+        Input::Mouse(MouseState { x, y, left_click, right_click })
+    }
+
+    #[test]
+    fn plain_keys() {
+        assert_eq!(parse_input(b"a"), Parsed::Input(key_with(KeyCode::Text, 'a', false, false), 1));
+        assert_eq!(parse_input(b"\r"), Parsed::Input(key_with(KeyCode::Enter, '\r', false, false), 1));
+        assert_eq!(parse_input(b" "), Parsed::Input(key_with(KeyCode::Spacebar, ' ', false, false), 1));
+        assert_eq!(parse_input(b"\x7f"), Parsed::Input(key_with(KeyCode::Backspace, '\0', false, false), 1));
+        assert_eq!(parse_input("é".as_bytes()), Parsed::Input(key_with(KeyCode::Text, 'é', false, false), 2));
+        // only the first key is read, the rest is left for later
+        assert_eq!(parse_input(b"jk"), Parsed::Input(key_with(KeyCode::Text, 'j', false, false), 1));
+    }
+
+    #[test]
+    fn arrow_keys() {
+        assert_eq!(parse_input(b"\x1b[A"), Parsed::Input(key_with(KeyCode::Up, '\0', false, false), 3));
+        assert_eq!(parse_input(b"\x1b[B"), Parsed::Input(key_with(KeyCode::Down, '\0', false, false), 3));
+        assert_eq!(parse_input(b"\x1b[C"), Parsed::Input(key_with(KeyCode::Right, '\0', false, false), 3));
+        assert_eq!(parse_input(b"\x1b[D"), Parsed::Input(key_with(KeyCode::Left, '\0', false, false), 3));
+        // application mode, as some terminals send them
+        assert_eq!(parse_input(b"\x1bOA"), Parsed::Input(key_with(KeyCode::Up, '\0', false, false), 3));
+        assert_eq!(parse_input(b"\x1bOr"), Parsed::Input(key_with(KeyCode::NumPad2, '\0', false, false), 3));
+    }
+
+    #[test]
+    fn special_keys() {
+        assert_eq!(parse_input(b"\x1b[5~"), Parsed::Input(key_with(KeyCode::PageUp, '\0', false, false), 4));
+        assert_eq!(parse_input(b"\x1b[20~"), Parsed::Input(key_with(KeyCode::F9, '\0', false, false), 5));
+        assert_eq!(parse_input(b"\x1b[H"), Parsed::Input(key_with(KeyCode::Home, '\0', false, false), 3));
+        assert_eq!(parse_input(b"\x1bOP"), Parsed::Input(key_with(KeyCode::F1, '\0', false, false), 3));
+        // unknown sequences are skipped whole
+        assert_eq!(parse_input(b"\x1b[99~x"), Parsed::Skip(5));
+        assert_eq!(parse_input(b"\x1bOZ"), Parsed::Skip(3));
+    }
+
+    #[test]
+    fn modifiers() {
+        assert_eq!(parse_input(b"\x1b[1;5C"), Parsed::Input(key_with(KeyCode::Right, '\0', true, false), 6));
+        assert_eq!(parse_input(b"\x1b[1;3A"), Parsed::Input(key_with(KeyCode::Up, '\0', false, true), 6));
+        assert_eq!(parse_input(b"\x1b[1;7D"), Parsed::Input(key_with(KeyCode::Left, '\0', true, true), 6));
+        assert_eq!(parse_input(b"\x1b[15;5~"), Parsed::Input(key_with(KeyCode::F5, '\0', true, false), 7));
+        // Ctrl+letter comes as a control character, Alt+key as Escape and the key
+        assert_eq!(parse_input(b"\x01"), Parsed::Input(key_with(KeyCode::Char, 'a', true, false), 1));
+        assert_eq!(parse_input(b"\x1bx"), Parsed::Input(key_with(KeyCode::Text, 'x', false, true), 2));
+        assert_eq!(parse_input(b"\x1b\x1b[A"), Parsed::Input(key_with(KeyCode::Up, '\0', false, true), 4));
+    }
+
+    #[test]
+    fn mouse_clicks() {
+        // columns and rows count from 1 in the terminal, from 0 in the game
+        assert_eq!(parse_input(b"\x1b[<0;10;5M"), Parsed::Input(mouse(9, 4, true, false), 10));
+        assert_eq!(parse_input(b"\x1b[<2;80;50M"), Parsed::Input(mouse(79, 49, false, true), 11));
+        // releasing a button, moving and the wheel don't click
+        assert_eq!(parse_input(b"\x1b[<0;10;5m"), Parsed::Input(mouse(9, 4, false, false), 10));
+        assert_eq!(parse_input(b"\x1b[<35;3;4M"), Parsed::Input(mouse(2, 3, false, false), 10));
+        assert_eq!(parse_input(b"\x1b[<64;3;4M"), Parsed::Input(mouse(2, 3, false, false), 10));
+        // a click with a modifier held is still a click
+        assert_eq!(parse_input(b"\x1b[<16;1;1M"), Parsed::Input(mouse(0, 0, true, false), 10));
+    }
+
+    #[test]
+    fn broken_mouse_events_are_skipped() {
+        assert_eq!(parse_input(b"\x1b[<0;10M"), Parsed::Skip(8));
+        assert_eq!(parse_input(b"\x1b[<0;x;5M"), Parsed::Skip(9));
+        // without an end in sight, only the start is dropped
+        assert_eq!(parse_input(b"\x1b[<0;xq"), Parsed::Skip(3));
+    }
+
+    #[test]
+    fn truncated_sequences_wait_for_the_rest() {
+        for bytes in [
+            &b"\x1b"[..],
+            b"\x1b[",
+            b"\x1b[1;",
+            b"\x1b[15",
+            b"\x1bO",
+            b"\x1b[<",
+            b"\x1b[<0;10",
+            b"\x1b[<0;10;5",
+            b"\x1b\x1b[",
+            &"é".as_bytes()[..1],
+            b"",
+        ]
+        .iter()
+        {
+            assert_eq!(parse_input(bytes), Parsed::Incomplete, "{:?}", bytes);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use tcod::console::*;
+use tcod::input::{self, Event, KeyCode};
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::backend::Backend;
+
+/// the regular game window, drawn by libtcod
+pub struct WindowBackend {
+    root: Root,
+}
+
+impl WindowBackend {
+    pub fn new() -> Self {
+        tcod::system::set_fps(LIMIT_FPS);
+
+        let root = Root::initializer()
+            .font(FONT_FILE, FontLayout::Tcod)
+            .font_type(FontType::Greyscale)
+            .size(SCREEN_WIDTH, SCREEN_HEIGHT)
+            .title("ARROBA")
+            .init();
+        WindowBackend { root }
+    }
+}
+
+impl Backend for WindowBackend {
+    fn present(&mut self, screen: &Canvas) {
+        for y in 0..screen.height {
+            for x in 0..screen.width {
+                let cell = screen.cells[(y * screen.width + x) as usize];
+                self.root.put_char_ex(x, y, cell.ch, cell.fg, cell.bg);
+            }
+        }
+        self.root.flush();
+    }
+
+    fn poll_input(&mut self) -> Option<Input> {
+        match input::check_for_event(input::MOUSE | input::KEY_PRESS).map(|e| e.1) {
+            Some(Event::Mouse(m)) => Some(Input::Mouse(MouseState {
+                x: m.cx as i32,
+                y: m.cy as i32,
+                left_click: m.lbutton_pressed,
+                right_click: m.rbutton_pressed,
+            })),
+            Some(Event::Key(k)) => Some(Input::Key(key_press(k))),
+            None => None,
+        }
+    }
+
+    fn wait_for_key(&mut self) -> KeyPress {
+        key_press(self.root.wait_for_keypress(true))
+    }
+
+    fn closed(&self) -> bool {
+        self.root.window_closed()
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        let fullscreen = self.root.is_fullscreen();
+        self.root.set_fullscreen(!fullscreen);
+    }
+
+    fn draw_image(&mut self, path: &str, screen: &mut Canvas) {
+        let img = tcod::image::Image::from_file(path).expect("Background image not found");
+        // show the image at twice the regular console resolution, then read back what it became
+        tcod::image::blit_2x(&img, (0, 0), (-1, -1), &mut self.root, (0, 0));
+        for y in 0..screen.height {
+            for x in 0..screen.width {
+                if let Some(cell) = screen.cell_mut(x, y) {
+                    cell.ch = self.root.get_char(x, y);
+                    cell.fg = self.root.get_char_foreground(x, y);
+                    cell.bg = self.root.get_char_background(x, y);
+                }
+            }
+        }
+    }
+}
+
+fn key_press(key: input::Key) -> KeyPress {
+    KeyPress {
+        code: key.code,
+        // typed text comes as a string, everything else as a single character
+        printable: match key.code {
+            KeyCode::Text => key.text().chars().next().unwrap_or('\0'),
+            _ => key.printable,
+        },
+        ctrl: key.ctrl,
+        alt: key.alt,
+    }
+}
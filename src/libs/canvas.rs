@@ -0,0 +1,165 @@
+use tcod::colors::{self, Color, BLACK, WHITE};
+use tcod::console::TextAlignment;
+
+use crate::predefs::structs::*;
+
+impl Canvas {
+    pub fn new(width: i32, height: i32) -> Self {
+        Canvas {
+            width,
+            height,
+            cells: vec![BLANK; (width * height) as usize],
+        }
+    }
+
+    /// blank out everything: spaces, white on black
+    pub fn clear(&mut self) {
+        self.cells.iter_mut().for_each(|cell| *cell = BLANK);
+    }
+
+    pub fn cell(&self, x: i32, y: i32) -> Option<&Cell> {
+        self.index(x, y).map(|i| &self.cells[i])
+    }
+
+    pub fn cell_mut(&mut self, x: i32, y: i32) -> Option<&mut Cell> {
+        self.index(x, y).map(move |i| &mut self.cells[i])
+    }
+
+    /// draw a character, keeping the background; anything off the canvas is ignored
+    pub fn put_char(&mut self, x: i32, y: i32, ch: char, fg: Color) {
+        if let Some(cell) = self.cell_mut(x, y) {
+            cell.ch = ch;
+            cell.fg = fg;
+        }
+    }
+
+    pub fn set_background(&mut self, x: i32, y: i32, bg: Color) {
+        if let Some(cell) = self.cell_mut(x, y) {
+            cell.bg = bg;
+        }
+    }
+
+    pub fn fill_background(&mut self, x: i32, y: i32, width: i32, height: i32, bg: Color) {
+        for y in y..y + height {
+            for x in x..x + width {
+                self.set_background(x, y, bg);
+            }
+        }
+    }
+
+    /// a single line of text, left aligned at `x` or centered on it
+    pub fn print(&mut self, x: i32, y: i32, text: &str, fg: Color, alignment: TextAlignment) {
+        let length = text.chars().count() as i32;
+        let start = match alignment {
+            TextAlignment::Center => x - length / 2,
+            TextAlignment::Right => x - length + 1,
+            TextAlignment::Left => x,
+        };
+        for (i, ch) in text.chars().enumerate() {
+            self.put_char(start + i as i32, y, ch, fg);
+        }
+    }
+
+    /// text wrapped to `width` columns; returns how many lines it took
+    pub fn print_rect(&mut self, x: i32, y: i32, width: i32, text: &str, fg: Color) -> i32 {
+        let lines = wrap(text, width);
+        for (i, line) in lines.iter().enumerate() {
+            self.print(x, y + i as i32, line, fg, TextAlignment::Left);
+        }
+        lines.len() as i32
+    }
+
+    /// copy all of this canvas onto another one, mixing in `bg_alpha` of its background
+    pub fn blit(&self, dest: &mut Canvas, x: i32, y: i32, bg_alpha: f32) {
+        for src_y in 0..self.height {
+            for src_x in 0..self.width {
+                let cell = self.cells[(src_y * self.width + src_x) as usize];
+                if let Some(target) = dest.cell_mut(x + src_x, y + src_y) {
+                    target.ch = cell.ch;
+                    target.fg = cell.fg;
+                    target.bg = colors::lerp(target.bg, cell.bg, bg_alpha);
+                }
+            }
+        }
+    }
+
+    /// the characters on the canvas, one line per row
+    pub fn text(&self) -> String {
+        self.rows()
+            .map(|row| row.iter().map(|cell| cell.ch).collect::<String>().trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// the background colours, one letter per distinct colour (black is a space),
+    /// followed by the colour each letter stands for
+    pub fn background_text(&self) -> String {
+        let mut colors = vec![BLACK];
+        let mut lines = vec![];
+        for row in self.rows() {
+            let line: String = row
+                .iter()
+                .map(|cell| {
+                    let index = colors.iter().position(|&color| color == cell.bg).unwrap_or_else(|| {
+                        colors.push(cell.bg);
+                        colors.len() - 1
+                    });
+                    COLOR_LETTERS.chars().nth(index).unwrap_or('?')
+                })
+                .collect();
+            lines.push(line.trim_end().to_string());
+        }
+        for (letter, color) in COLOR_LETTERS.chars().zip(&colors).skip(1) {
+            lines.push(format!("{} = #{:02x}{:02x}{:02x}", letter, color.r, color.g, color.b));
+        }
+        lines.join("\n")
+    }
+
+    fn rows(&self) -> impl Iterator<Item = &[Cell]> {
+        self.cells.chunks(self.width as usize)
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x >= 0 && y >= 0 && x < self.width && y < self.height {
+            Some((y * self.width + x) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+const BLANK: Cell = Cell {
+    ch: ' ',
+    fg: WHITE,
+    bg: BLACK,
+};
+
+/// stand-ins for colours in text snapshots; the first one is black
+const COLOR_LETTERS: &str = " abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+/// how many lines `text` takes once wrapped to `width` columns
+pub fn height_rect(width: i32, text: &str) -> i32 {
+    wrap(text, width).len() as i32
+}
+
+/// split text into lines of at most `width` characters, breaking between words
+/// when possible; newlines always start a new line
+pub fn wrap(text: &str, width: i32) -> Vec<String> {
+    let width = width.max(1) as usize;
+    let mut lines = vec![];
+    for paragraph in text.split('\n') {
+        let mut rest: Vec<char> = paragraph.chars().collect();
+        while rest.len() > width {
+            let cut = rest[..=width]
+                .iter()
+                .rposition(|&ch| ch == ' ')
+                .filter(|&cut| cut > 0)
+                .unwrap_or(width);
+            lines.push(rest[..cut].iter().collect());
+            let next_word = rest[cut..].iter().position(|&ch| ch != ' ').unwrap_or(rest.len() - cut);
+            rest.drain(..cut + next_word);
+        }
+        lines.push(rest.into_iter().collect());
+    }
+    lines
+}
@@ -0,0 +1,272 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+
+use serde::Deserialize;
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::make_map::from_dungeon_level;
+
+/// read and check all the game data, so mistakes show up at startup and not mid-game
+pub fn load_content() -> Result<Content, Box<dyn Error>> {
+    let monsters: Vec<MonsterDef> = read_json(MONSTERS_FILE)?;
+    validate_monsters(&monsters).map_err(|e| format!("{}: {}", MONSTERS_FILE, e))?;
+    let items: ItemsFile = read_json(ITEMS_FILE)?;
+    validate_items(&items).map_err(|e| format!("{}: {}", ITEMS_FILE, e))?;
+    let vaults: Vec<VaultDef> = read_json(VAULTS_FILE)?;
+    validate_vaults(&vaults, &monsters, &items.items).map_err(|e| format!("{}: {}", VAULTS_FILE, e))?;
+    let traps: Vec<TrapDef> = read_json(TRAPS_FILE)?;
+    validate_traps(&traps).map_err(|e| format!("{}: {}", TRAPS_FILE, e))?;
+    let themes: Vec<ThemeDef> = read_json(THEMES_FILE)?;
+    validate_themes(&themes, &monsters, &items.items).map_err(|e| format!("{}: {}", THEMES_FILE, e))?;
+    Ok(Content {
+        monsters,
+        items: items.items,
+        starting_inventory: items.starting_inventory,
+        vaults,
+        traps,
+        themes,
+    })
+}
+
+#[derive(Deserialize)]
+struct ItemsFile {
+    starting_inventory: Vec<String>,
+    items: Vec<ItemDef>,
+}
+
+pub fn read_json<T: serde::de::DeserializeOwned>(path: &str) -> Result<T, Box<dyn Error>> {
+    let mut json = String::new();
+    let mut file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    file.read_to_string(&mut json)?;
+    let data = serde_json::from_str(&json).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(data)
+}
+
+fn validate_monsters(monsters: &[MonsterDef]) -> Result<(), String> {
+    if monsters.is_empty() {
+        return Err("there must be at least one monster".into());
+    }
+    let mut ids = HashSet::new();
+    for monster in monsters {
+        if monster.id.is_empty() || !ids.insert(&monster.id) {
+            return Err(format!("monster id \"{}\" is empty or used twice", monster.id));
+        }
+        if monster.hp <= 0 || monster.defense < 0 || monster.power < 0 || monster.xp < 0 {
+            return Err(format!("{} needs positive hp and no negative stats", monster.id));
+        }
+        if let Ai::Confused { .. } | Ai::Alerted { .. } = monster.ai {
+            return Err(format!("{} can't start out confused or alerted", monster.id));
+        }
+        validate_spawn_table(&monster.spawn).map_err(|e| format!("{}: {}", monster.id, e))?;
+    }
+    let spawn_tables: Vec<_> = monsters.iter().map(|monster| &monster.spawn[..]).collect();
+    validate_every_level(&spawn_tables).map_err(|level| format!("no monster can spawn on dungeon level {}", level))
+}
+
+fn validate_items(file: &ItemsFile) -> Result<(), String> {
+    let items = &file.items;
+    if items.is_empty() {
+        return Err("there must be at least one item".into());
+    }
+    let mut ids = HashSet::new();
+    for item in items {
+        if item.id.is_empty() || !ids.insert(&item.id) {
+            return Err(format!("item id \"{}\" is empty or used twice", item.id));
+        }
+        let valid_effect = match item.effect {
+            Item::Heal { amount } => amount > 0,
+            Item::Lightning { damage, range } => damage > 0 && range > 0,
+            Item::Confusion { range, turns } => range > 0 && turns > 0,
+            Item::Fireball { damage, radius } => damage > 0 && radius >= 0,
+            Item::Equipment => item.equipment.is_some(),
+        };
+        if !valid_effect {
+            return Err(format!("{} has an invalid {:?} effect", item.id, item.effect));
+        }
+        if item.equipment.is_some() && item.effect != Item::Equipment {
+            return Err(format!("{} has equipment bonuses, so its effect must be Equipment", item.id));
+        }
+        // items that are only handed out (like the starting dagger) have no spawn table
+        if !item.spawn.is_empty() {
+            validate_spawn_table(&item.spawn).map_err(|e| format!("{}: {}", item.id, e))?;
+        }
+    }
+    if let Some(id) = file.starting_inventory.iter().find(|id| !ids.contains(id)) {
+        return Err(format!("unknown starting item \"{}\"", id));
+    }
+    let spawn_tables: Vec<_> = items.iter().map(|item| &item.spawn[..]).collect();
+    validate_every_level(&spawn_tables).map_err(|level| format!("no item can spawn on dungeon level {}", level))
+}
+
+fn validate_vaults(vaults: &[VaultDef], monsters: &[MonsterDef], items: &[ItemDef]) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    for vault in vaults {
+        if vault.id.is_empty() || !ids.insert(&vault.id) {
+            return Err(format!("vault id \"{}\" is empty or used twice", vault.id));
+        }
+        if vault.rarity == 0 {
+            return Err(format!("{} needs a rarity of 1 or more", vault.id));
+        }
+        if vault.depth.min < 1 || vault.depth.max.is_some_and(|max| max < vault.depth.min) {
+            return Err(format!("{} has depths that start below 1 or end before they start", vault.id));
+        }
+        for (&ch, id) in &vault.legend {
+            if VAULT_TILES.contains(ch) {
+                return Err(format!("{} can't use '{}' in its legend, it is already a map tile", vault.id, ch));
+            }
+            if !monsters.iter().any(|monster| &monster.id == id) && !items.iter().any(|item| &item.id == id) {
+                return Err(format!("{}: '{}' stands for \"{}\", which is not a monster or an item", vault.id, ch, id));
+            }
+        }
+        validate_vault_map(vault).map_err(|e| format!("{}: {}", vault.id, e))?;
+    }
+    Ok(())
+}
+
+fn validate_vault_map(vault: &VaultDef) -> Result<(), String> {
+    let rows: Vec<Vec<char>> = vault.map.iter().map(|row| row.chars().collect()).collect();
+    let width = rows.first().map_or(0, |row| row.len());
+    if width == 0 || rows.iter().any(|row| row.len() != width) {
+        return Err("the map rows must all be the same length, and not empty".into());
+    }
+    // it can be turned on its side, and it needs rock all around it
+    if width.max(rows.len()) as i32 > MAP_HEIGHT - 4 {
+        return Err("the map is too big to fit in a level".into());
+    }
+    if let Some(&ch) = rows.iter().flatten().find(|&&ch| !VAULT_TILES.contains(ch) && !vault.legend.contains_key(&ch)) {
+        return Err(format!("unknown map character '{}'", ch));
+    }
+    let outside = |x: i32, y: i32| {
+        rows.get(y as usize).and_then(|row| row.get(x as usize)).is_none_or(|&ch| ch == ' ')
+    };
+    let mut doors = 0;
+    for (y, row) in rows.iter().enumerate() {
+        for (x, &ch) in row.iter().enumerate() {
+            if ch != '+' {
+                continue;
+            }
+            doors += 1;
+            let (x, y) = (x as i32, y as i32);
+            if ![(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)].iter().any(|&(x, y)| outside(x, y)) {
+                return Err(format!("the way in at row {}, column {} doesn't lead outside", y + 1, x + 1));
+            }
+        }
+    }
+    if doors == 0 {
+        return Err("there must be at least one way in ('+')".into());
+    }
+    Ok(())
+}
+
+/// levels can do without traps, so unlike monsters and items they needn't cover every level
+fn validate_traps(traps: &[TrapDef]) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    for trap in traps {
+        if trap.id.is_empty() || !ids.insert(&trap.id) {
+            return Err(format!("trap id \"{}\" is empty or used twice", trap.id));
+        }
+        let valid_effect = match trap.effect {
+            TrapEffect::Dart { damage } => damage > 0,
+            TrapEffect::Confusion { turns } => turns > 0,
+            TrapEffect::Alarm { radius } => radius > 0,
+            TrapEffect::Teleport | TrapEffect::Trapdoor => true,
+        };
+        if !valid_effect {
+            return Err(format!("{} has an invalid {:?} effect", trap.id, trap.effect));
+        }
+        validate_spawn_table(&trap.spawn).map_err(|e| format!("{}: {}", trap.id, e))?;
+    }
+    Ok(())
+}
+
+fn validate_themes(themes: &[ThemeDef], monsters: &[MonsterDef], items: &[ItemDef]) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    for theme in themes {
+        if theme.id.is_empty() || !ids.insert(&theme.id) {
+            return Err(format!("theme id \"{}\" is empty or used twice", theme.id));
+        }
+        validate_spawn_table(&theme.spawn).map_err(|e| format!("{}: {}", theme.id, e))?;
+        validate_theme(theme, monsters, items).map_err(|e| format!("{}: {}", theme.id, e))?;
+    }
+    let spawn_tables: Vec<_> = themes.iter().map(|theme| &theme.spawn[..]).collect();
+    validate_every_level(&spawn_tables).map_err(|level| format!("no theme can be picked on dungeon level {}", level))
+}
+
+fn validate_theme(theme: &ThemeDef, monsters: &[MonsterDef], items: &[ItemDef]) -> Result<(), String> {
+    if !theme.generators.iter().any(|generator| generator.weight > 0) {
+        return Err("there must be a generator with a weight above 0".into());
+    }
+    for generator in &theme.generators {
+        let valid = match generator.kind {
+            GeneratorKind::Rooms { max_rooms, room_min_size, room_max_size } => {
+                max_rooms > 0 && 3 <= room_min_size && room_min_size <= room_max_size && room_max_size < MAP_HEIGHT - 2
+            }
+            GeneratorKind::Bsp { min_leaf, max_leaf, min_room, loop_chance } => {
+                3 <= min_room && min_room <= min_leaf && min_leaf <= max_leaf && (0.0..=1.0).contains(&loop_chance)
+            }
+            GeneratorKind::Caves { wall_chance, min_floor, .. } => {
+                (0.0..1.0).contains(&wall_chance) && min_floor < ((MAP_WIDTH - 2) * (MAP_HEIGHT - 2)) as usize
+            }
+        };
+        if !valid {
+            return Err(format!("invalid generator settings {:?}", generator.kind));
+        }
+    }
+    if let Some(hazards) = theme.hazards {
+        if hazards.max_pools > 0 && hazards.water + hazards.lava + hazards.chasm == 0 {
+            return Err("pools need a kind with a weight above 0".into());
+        }
+    }
+    if let Some(id) = theme.monsters.keys().find(|id| !monsters.iter().any(|monster| &monster.id == *id)) {
+        return Err(format!("unknown monster \"{}\"", id));
+    }
+    if let Some(id) = theme.items.keys().find(|id| !items.iter().any(|item| &item.id == *id)) {
+        return Err(format!("unknown item \"{}\"", id));
+    }
+    // an empty table keeps something out of the theme altogether
+    for (id, table) in theme.monsters.iter().chain(&theme.items) {
+        if !table.is_empty() {
+            validate_spawn_table(table).map_err(|e| format!("{}: {}", id, e))?;
+        }
+    }
+    // from the first level it can be picked on, there must still be something to spawn
+    let monster_tables: Vec<_> = monsters.iter().map(|monster| theme.monster_spawn(monster)).collect();
+    let item_tables: Vec<_> = items.iter().map(|item| theme.item_spawn(item)).collect();
+    for (tables, what) in [(&monster_tables, "monster"), (&item_tables, "item")].iter() {
+        let last_level = tables.iter().flat_map(|table| table.iter().map(|t| t.level)).max().unwrap_or(1);
+        for level in theme.spawn[0].level..=last_level.max(theme.spawn[0].level) {
+            if tables.iter().all(|table| from_dungeon_level(table, level) == 0) {
+                return Err(format!("no {} can spawn on dungeon level {}", what, level));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// every dungeon level needs something to spawn; the tables don't change past their last level
+fn validate_every_level(spawn_tables: &[&[Transition]]) -> Result<(), u32> {
+    let last_level = spawn_tables
+        .iter()
+        .flat_map(|table| table.iter().map(|t| t.level))
+        .max()
+        .unwrap_or(1);
+    for level in 1..=last_level {
+        if spawn_tables.iter().all(|table| from_dungeon_level(table, level) == 0) {
+            return Err(level);
+        }
+    }
+    Ok(())
+}
+
+fn validate_spawn_table(table: &[Transition]) -> Result<(), String> {
+    if table.is_empty() {
+        return Err("the spawn table is empty, so it would never show up".into());
+    }
+    if table[0].level < 1 || table.windows(2).any(|pair| pair[0].level >= pair[1].level) {
+        return Err("spawn levels must start at 1 or more and keep increasing".into());
+    }
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tcod::colors::{self, Color};
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::save::user_data_dir;
+
+/// write the canvas as a PNG picture, an ANSI text file and an HTML page, side by side
+/// in the screenshots directory; returns the path without the extension
+pub fn export_canvas(canvas: &Canvas, name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let directory = user_data_dir().join("screenshots");
+    fs::create_dir_all(&directory)?;
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    let path = directory.join(format!("{}-{}", name, time));
+
+    fs::write(path.with_extension("ans"), to_ansi(canvas))?;
+    fs::write(path.with_extension("html"), to_html(canvas))?;
+    write_png(canvas, &path.with_extension("png"))?;
+    Ok(path)
+}
+
+/// 24-bit colour escape codes, as understood by most terminals (`cat` the file)
+pub fn to_ansi(canvas: &Canvas) -> String {
+    let mut out = String::new();
+    for y in 0..canvas.height {
+        let mut colors = None;
+        for x in 0..canvas.width {
+            let cell = canvas.cells[(y * canvas.width + x) as usize];
+            if colors != Some((cell.fg, cell.bg)) {
+                let (fg, bg) = (cell.fg, cell.bg);
+                let _ = write!(out, "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m", fg.r, fg.g, fg.b, bg.r, bg.g, bg.b);
+                colors = Some((fg, bg));
+            }
+            out.push(cell.ch);
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+/// a page with nothing to load besides itself
+pub fn to_html(canvas: &Canvas) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>A Robber</title>\n</head>\n\
+         <body style=\"background: #000\">\n<pre style=\"font-family: monospace; line-height: 1.1\">",
+    );
+    for y in 0..canvas.height {
+        let row = &canvas.cells[(y * canvas.width) as usize..((y + 1) * canvas.width) as usize];
+        // one span for every run of cells with the same colours
+        for run in row.chunk_by(|a, b| (a.fg, a.bg) == (b.fg, b.bg)) {
+            let _ = write!(
+                out,
+                "<span style=\"color: {}; background: {}\">",
+                hex(run[0].fg),
+                hex(run[0].bg)
+            );
+            for cell in run {
+                match cell.ch {
+                    '<' => out.push_str("&lt;"),
+                    '>' => out.push_str("&gt;"),
+                    '&' => out.push_str("&amp;"),
+                    ch => out.push(ch),
+                }
+            }
+            out.push_str("</span>");
+        }
+        out.push('\n');
+    }
+    out.push_str("</pre>\n</body>\n</html>\n");
+    out
+}
+
+fn hex(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+/// draw every cell with the game's own font, just like the window shows it
+fn write_png(canvas: &Canvas, path: &PathBuf) -> Result<(), Box<dyn Error>> {
+    let font = Font::load(FONT_FILE)?;
+    let (width, height) = (canvas.width as usize * font.cell_width, canvas.height as usize * font.cell_height);
+    let mut pixels = vec![0u8; width * height * 3];
+    for y in 0..canvas.height as usize {
+        for x in 0..canvas.width as usize {
+            let cell = canvas.cells[y * canvas.width as usize + x];
+            for glyph_y in 0..font.cell_height {
+                for glyph_x in 0..font.cell_width {
+                    // the font is greyscale: how bright a pixel is says how much of it is glyph
+                    let coverage = font.coverage(cell.ch, glyph_x, glyph_y);
+                    let color = colors::lerp(cell.bg, cell.fg, coverage);
+                    let pixel_x = x * font.cell_width + glyph_x;
+                    let pixel_y = y * font.cell_height + glyph_y;
+                    let i = (pixel_y * width + pixel_x) * 3;
+                    pixels[i..i + 3].copy_from_slice(&[color.r, color.g, color.b]);
+                }
+            }
+        }
+    }
+    let mut encoder = png::Encoder::new(File::create(path)?, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+    Ok(())
+}
+
+/// a font picture in libtcod's layout: 32 glyphs per row, 8 rows
+struct Font {
+    cell_width: usize,
+    cell_height: usize,
+    width: usize,
+    /// one brightness value per pixel
+    pixels: Vec<u8>,
+}
+
+impl Font {
+    fn load(path: &str) -> Result<Font, Box<dyn Error>> {
+        let decoder = png::Decoder::new(File::open(path).map_err(|e| format!("{}: {}", path, e))?);
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+        let channels = info.color_type.samples();
+        if info.bit_depth != png::BitDepth::Eight {
+            return Err(format!("{}: only 8-bit fonts are supported", path).into());
+        }
+        let width = info.width as usize;
+        let pixels = buffer[..info.buffer_size()].chunks(channels).map(|pixel| pixel[0]).collect();
+        Ok(Font {
+            cell_width: width / 32,
+            cell_height: info.height as usize / 8,
+            width,
+            pixels,
+        })
+    }
+
+    fn coverage(&self, ch: char, x: usize, y: usize) -> f32 {
+        let index = glyph_index(ch);
+        let pixel_x = index % 32 * self.cell_width + x;
+        let pixel_y = index / 32 * self.cell_height + y;
+        self.pixels.get(pixel_y * self.width + pixel_x).map_or(0.0, |&value| value as f32 / 255.0)
+    }
+}
+
+/// where libtcod's layout puts a character; anything else shows as a question mark
+fn glyph_index(ch: char) -> usize {
+    match ch {
+        ' '..='?' => ch as usize - ' ' as usize,
+        '@' => 32,
+        '['..='_' => 33 + (ch as usize - '[' as usize),
+        '`' => 38,
+        '{'..='~' => 39 + (ch as usize - '{' as usize),
+        'A'..='Z' => 96 + (ch as usize - 'A' as usize),
+        'a'..='z' => 128 + (ch as usize - 'a' as usize),
+        _ => glyph_index('?'),
+    }
+}
@@ -0,0 +1,412 @@
+use rand::Rng;
+use tcod::colors::*;
+use tcod::map::Map as FovMap;
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::make_map::*;
+use crate::libs::handle_keys::player_move_or_attack;
+use crate::libs::ai::*;
+use crate::libs::hazards::hazards_take_turn;
+use crate::libs::traps::{disarm, search, spot_traps};
+use crate::libs::secrets::spot_secrets;
+use crate::libs::menu::{use_item, drop_item};
+use crate::libs::save::delete_save;
+
+impl GameState {
+    pub fn new(game: Game, objects: Objects, content: Content) -> Self {
+        let mut state = GameState {
+            game,
+            objects,
+            fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
+            pending: None,
+            content,
+            save_slot: None,
+        };
+        state.recompute_fov();
+        state
+    }
+
+    /// advance the game by one player decision and report what happened
+    pub fn step(&mut self, command: PlayerCommand) -> Vec<GameEvent> {
+        self.game.commands.push(command);
+        let mut events = vec![];
+        let player_was_alive = self.objects[PLAYER].alive;
+        let dungeon_level = self.game.dungeon_level;
+
+        let player_action = match self.pending.take() {
+            Some(prompt) => self.answer(prompt, command),
+            None if player_was_alive => self.perform(command),
+            None => PlayerAction::DidntTakeTurn,
+        };
+        self.recompute_fov();
+
+        // let monsters take their turn
+        if self.objects[PLAYER].alive && player_action == PlayerAction::TookTurn {
+            events.push(GameEvent::TurnTaken);
+            self.game.turns += 1;
+            if self.game.confused_turns > 0 {
+                self.game.confused_turns -= 1;
+                if self.game.confused_turns == 0 {
+                    self.game.messages.add("Your head clears.", LIGHT_GREEN);
+                }
+            }
+            spot_traps(&self.fov, &mut self.game, &mut self.objects);
+            spot_secrets(&self.fov, &mut self.game, &self.objects);
+            for id in self.objects.ids() {
+                // an earlier monster may have removed this one
+                if self.objects.get(id).is_some_and(|object| object.ai.is_some()) {
+                    ai_take_turn(id, &self.fov, &mut self.game, &mut self.objects);
+                }
+            }
+            hazards_take_turn(&self.fov, &mut self.game, &mut self.objects);
+            // monsters may have opened doors
+            self.recompute_fov();
+        }
+
+        if self.game.dungeon_level != dungeon_level {
+            events.push(GameEvent::LevelChanged(self.game.dungeon_level));
+        }
+        if player_was_alive && !self.objects[PLAYER].alive {
+            events.push(GameEvent::PlayerDied);
+            self.forget_save();
+        }
+        if self.pending.is_none() && self.objects[PLAYER].alive && can_level_up(&self.objects[PLAYER]) {
+            self.pending = Some(Prompt::LevelUp);
+        }
+        if let Some(prompt) = self.pending {
+            events.push(GameEvent::Prompt(prompt));
+        }
+        events
+    }
+
+    fn perform(&mut self, command: PlayerCommand) -> PlayerAction {
+        use PlayerCommand::*;
+        let action = match command {
+            Move { dx, dy } => {
+                // one step at a time; standing still is just waiting
+                let (mut dx, mut dy) = (dx.signum(), dy.signum());
+                if (dx, dy) != (0, 0) {
+                    // a confused player stumbles off any which way
+                    if self.game.confused_turns > 0 {
+                        dx = self.game.rng.gen_range(-1, 2);
+                        dy = self.game.rng.gen_range(-1, 2);
+                    }
+                    player_move_or_attack(dx, dy, &self.fov, &mut self.game, &mut self.objects);
+                }
+                PlayerAction::TookTurn
+            }
+            Wait => PlayerAction::TookTurn, // do nothing, i.e. wait for the monster to come to you
+            PickUp => {
+                let player_pos = self.objects[PLAYER].pos();
+                let item_id = self
+                    .objects
+                    .iter()
+                    .find(|(_, object)| object.pos() == player_pos && object.item.is_some())
+                    .map(|(id, _)| id);
+                if let Some(item_id) = item_id {
+                    pick_item_up(item_id, &mut self.game, &mut self.objects);
+                }
+                PlayerAction::DidntTakeTurn
+            }
+            UseItem(inventory_id) if inventory_id < self.game.inventory.len() => {
+                match target_prompt(inventory_id, &self.game.inventory) {
+                    Some(prompt) => {
+                        let hint = match prompt {
+                            Prompt::Target { monster: true, .. } => {
+                                "Left-click an enemy to confuse it, or right-click to cancel."
+                            }
+                            _ => "Left-click a target tile for the fireball, or right-click to cancel.",
+                        };
+                        self.game.messages.add(hint, LIGHT_CYAN);
+                        self.pending = Some(prompt);
+                    }
+                    None => use_item(inventory_id, None, self),
+                }
+                PlayerAction::DidntTakeTurn
+            }
+            DropItem(inventory_id) if inventory_id < self.game.inventory.len() => {
+                drop_item(inventory_id, &mut self.game, &mut self.objects);
+                PlayerAction::DidntTakeTurn
+            }
+            Descend => {
+                // go down stairs, if the player is on them
+                if self.player_on("stairs down") {
+                    next_level(self);
+                }
+                PlayerAction::DidntTakeTurn
+            }
+            Ascend => {
+                if self.player_on("stairs up") {
+                    previous_level(self);
+                }
+                PlayerAction::DidntTakeTurn
+            }
+            CloseDoor => close_doors(&mut self.game, &self.objects),
+            Search => search(&self.fov, &mut self.game, &mut self.objects),
+            Disarm => disarm(&self.fov, &mut self.game, &mut self.objects),
+            _ => PlayerAction::DidntTakeTurn,
+        };
+        // walking into a chasm is one way down, a trapdoor opening underfoot is another
+        let (x, y) = self.objects[PLAYER].pos();
+        if self.objects[PLAYER].hazard_in(&self.game.map[x as usize][y as usize]) == Some(Hazard::Chasm) {
+            // the new level doesn't get to move before the player lands
+            fall(self);
+            return PlayerAction::DidntTakeTurn;
+        }
+        action
+    }
+
+    /// death is final, so the run can't be continued from its save
+    fn forget_save(&mut self) {
+        if let Some(slot) = self.save_slot.take() {
+            match delete_save(slot) {
+                Ok(()) | Err(SaveError::NotFound) => {}
+                Err(e) => self.game.messages.add(format!("Could not delete the saved game: {}", e), RED),
+            }
+        }
+    }
+
+    /// the player stands on an object with this name
+    fn player_on(&self, name: &str) -> bool {
+        let player_pos = self.objects[PLAYER].pos();
+        self.objects
+            .iter()
+            .any(|(_, object)| object.pos() == player_pos && object.name == name)
+    }
+
+    fn answer(&mut self, prompt: Prompt, command: PlayerCommand) -> PlayerAction {
+        match (prompt, command) {
+            (Prompt::LevelUp, PlayerCommand::LevelUp(stat)) => {
+                level_up(stat, &mut self.game, &mut self.objects);
+            }
+            (Prompt::Target { inventory_id, .. }, PlayerCommand::Target(target)) => {
+                let target = target.filter(|&target| self.valid_target(prompt, target));
+                use_item(inventory_id, target, self);
+            }
+            // anything else does not answer the question, so keep asking
+            _ => self.pending = Some(prompt),
+        }
+        PlayerAction::DidntTakeTurn
+    }
+
+    fn valid_target(&self, prompt: Prompt, (x, y): (i32, i32)) -> bool {
+        if let Prompt::Target { max_range, monster, .. } = prompt {
+            let in_map = x >= 0 && y >= 0 && x < MAP_WIDTH && y < MAP_HEIGHT;
+            let in_fov = in_map && self.fov.is_in_fov(x, y);
+            let in_range = max_range.is_none_or(|range| self.objects[PLAYER].distance(x, y) <= range);
+            let has_monster = !monster || monster_at(x, y, &self.objects).is_some();
+            in_fov && in_range && has_monster
+        } else {
+            false
+        }
+    }
+
+    /// recompute the player's field of view and explore whatever became visible
+    pub fn recompute_fov(&mut self) {
+        // doors open and close, so what can be seen through changes
+        initialise_fov(&mut self.fov, &self.game.map);
+        let player = &self.objects[PLAYER];
+        self.fov
+            .compute_fov(player.x, player.y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+        for y in 0..MAP_HEIGHT {
+            for x in 0..MAP_WIDTH {
+                if self.fov.is_in_fov(x, y) {
+                    self.game.map[x as usize][y as usize].explored = true;
+                }
+            }
+        }
+    }
+}
+
+/// the question to ask before using an item, if it needs a target
+fn target_prompt(inventory_id: usize, inventory: &[Object]) -> Option<Prompt> {
+    match inventory[inventory_id].item {
+        Some(Item::Confusion { range, .. }) => Some(Prompt::Target {
+            inventory_id,
+            max_range: Some(range as f32),
+            monster: true,
+        }),
+        Some(Item::Fireball { .. }) => Some(Prompt::Target {
+            inventory_id,
+            max_range: None,
+            monster: false,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::content::load_content;
+    use crate::libs::menu::new_game;
+    use crate::libs::save::{save_game, save_summary, use_test_data_dir};
+    use crate::libs::traps::spring_trap;
+
+    fn seeded_game() -> GameState {
+        let content = load_content().expect("the game data loads");
+        new_game(42, &content)
+    }
+
+    fn position_of(state: &GameState, name: &str) -> (i32, i32) {
+        state
+            .objects
+            .iter()
+            .find(|(_, object)| object.name == name)
+            .map(|(_, object)| object.pos())
+            .unwrap()
+    }
+
+    #[test]
+    fn waiting_takes_a_turn() {
+        let mut state = seeded_game();
+        let events = state.step(PlayerCommand::Wait);
+        assert_eq!(events, vec![GameEvent::TurnTaken]);
+        assert_eq!(state.game.turns, 1);
+        assert_eq!(state.game.commands, vec![PlayerCommand::Wait]);
+    }
+
+    #[test]
+    fn stairs_change_level_both_ways() {
+        let mut state = seeded_game();
+        let (x, y) = position_of(&state, "stairs down");
+        state.objects[PLAYER].set_pos(x, y);
+        let events = state.step(PlayerCommand::Descend);
+        assert!(events.contains(&GameEvent::LevelChanged(2)));
+        assert!(!events.contains(&GameEvent::TurnTaken));
+        assert_eq!(state.game.dungeon_level, 2);
+
+        // the player arrives on the way back up
+        let events = state.step(PlayerCommand::Ascend);
+        assert!(events.contains(&GameEvent::LevelChanged(1)));
+        assert_eq!(state.objects[PLAYER].pos(), (x, y));
+    }
+
+    #[test]
+    fn stairs_do_nothing_elsewhere() {
+        let mut state = seeded_game();
+        assert_eq!(state.step(PlayerCommand::Descend), vec![]);
+        assert_eq!(state.step(PlayerCommand::Ascend), vec![]);
+        assert_eq!(state.game.dungeon_level, 1);
+    }
+
+    #[test]
+    fn trapdoors_sprung_underfoot_drop_the_player() {
+        let mut state = seeded_game();
+        let (x, y) = state.objects[PLAYER].pos();
+        let mut trapdoor = Object::new(x, y, '^', "trapdoor", ORANGE, false);
+        trapdoor.trap = Some(Trap { effect: TrapEffect::Trapdoor, hidden: false });
+        let trap_id = state.objects.insert(trapdoor);
+        // like a fumbled disarm: the floor opens, and the player falls once done acting
+        spring_trap(trap_id, PLAYER, &state.fov, &mut state.game, &mut state.objects);
+        let events = state.step(PlayerCommand::Wait);
+        assert!(events.contains(&GameEvent::LevelChanged(2)));
+        let (x, y) = state.objects[PLAYER].pos();
+        assert!(state.game.map[x as usize][y as usize].safe());
+    }
+
+    #[test]
+    fn death_is_reported_once() {
+        let mut state = seeded_game();
+        let (x, y) = state.objects[PLAYER].pos();
+        state.game.map[x as usize][y as usize].kind = TileKind::Hazard(Hazard::Lava);
+        state.objects[PLAYER].fighter.as_mut().unwrap().hp = LAVA_DAMAGE;
+        let events = state.step(PlayerCommand::Wait);
+        assert_eq!(events, vec![GameEvent::TurnTaken, GameEvent::PlayerDied]);
+        assert!(!state.objects[PLAYER].alive);
+
+        // the dead don't take turns
+        assert_eq!(state.step(PlayerCommand::Wait), vec![]);
+        assert_eq!(state.game.turns, 1);
+    }
+
+    #[test]
+    fn lava_out_of_sight_burns_quietly() {
+        let mut state = seeded_game();
+        let unseen = state
+            .objects
+            .iter()
+            .find(|(_, object)| object.ai.is_some() && !state.fov.is_in_fov(object.x, object.y))
+            .map(|(id, _)| id)
+            .unwrap();
+        let (x, y) = state.objects[unseen].pos();
+        state.game.map[x as usize][y as usize].kind = TileKind::Hazard(Hazard::Lava);
+        let hp = state.objects[unseen].fighter.unwrap().hp;
+        let messages = state.game.messages.iter().count();
+        state.step(PlayerCommand::Wait);
+        assert!(state.objects[unseen].fighter.unwrap().hp < hp);
+        assert_eq!(state.game.messages.iter().count(), messages);
+    }
+
+    #[test]
+    fn death_deletes_the_save() {
+        let directory = std::env::temp_dir().join(format!("a-robber-test-{}", std::process::id()));
+        use_test_data_dir(directory.clone());
+        let mut state = seeded_game();
+        save_game(0, SaveFormat::Json, &state.game, &state.objects).unwrap();
+        state.save_slot = Some(0);
+        let (x, y) = state.objects[PLAYER].pos();
+        state.game.map[x as usize][y as usize].kind = TileKind::Hazard(Hazard::Lava);
+        state.objects[PLAYER].fighter.as_mut().unwrap().hp = LAVA_DAMAGE;
+        assert!(state.step(PlayerCommand::Wait).contains(&GameEvent::PlayerDied));
+        assert!(matches!(save_summary(0), Err(SaveError::NotFound)));
+        assert_eq!(state.save_slot, None);
+        let _ = std::fs::remove_dir_all(directory);
+    }
+
+    #[test]
+    fn level_up_keeps_asking_until_answered() {
+        let mut state = seeded_game();
+        let xp = level_up_xp(&state.objects[PLAYER]);
+        state.objects[PLAYER].fighter.as_mut().unwrap().xp = xp;
+        let events = state.step(PlayerCommand::Wait);
+        assert_eq!(events, vec![GameEvent::TurnTaken, GameEvent::Prompt(Prompt::LevelUp)]);
+
+        // anything but an answer leaves the question open, and the game waiting
+        let events = state.step(PlayerCommand::Wait);
+        assert_eq!(events, vec![GameEvent::Prompt(Prompt::LevelUp)]);
+        assert_eq!(state.game.turns, 1);
+
+        let power = state.objects[PLAYER].fighter.unwrap().base_power;
+        assert_eq!(state.step(PlayerCommand::LevelUp(Stat::Strength)), vec![]);
+        assert_eq!(state.objects[PLAYER].level, 2);
+        assert_eq!(state.objects[PLAYER].fighter.unwrap().base_power, power + 1);
+    }
+
+    #[test]
+    fn targeted_items_ask_for_a_target() {
+        let mut state = seeded_game();
+        let fireball = state.content.item("scroll_of_fireball").unwrap().create(0, 0);
+        state.game.inventory.push(fireball);
+        let inventory_id = state.game.inventory.len() - 1;
+        let prompt = Prompt::Target {
+            inventory_id,
+            max_range: None,
+            monster: false,
+        };
+        assert_eq!(state.step(PlayerCommand::UseItem(inventory_id)), vec![GameEvent::Prompt(prompt)]);
+        assert_eq!(state.step(PlayerCommand::Wait), vec![GameEvent::Prompt(prompt)]);
+
+        // cancelling keeps the scroll
+        assert_eq!(state.step(PlayerCommand::Target(None)), vec![]);
+        assert_eq!(state.game.inventory.len(), inventory_id + 1);
+        assert_eq!(state.step(PlayerCommand::Wait), vec![GameEvent::TurnTaken]);
+    }
+
+    #[test]
+    fn same_seed_same_run() {
+        let commands = [
+            PlayerCommand::Move { dx: 1, dy: 0 },
+            PlayerCommand::Move { dx: 0, dy: 1 },
+            PlayerCommand::Search,
+            PlayerCommand::Move { dx: -1, dy: -1 },
+        ];
+        let (mut first, mut second) = (seeded_game(), seeded_game());
+        for &command in commands.iter().cycle().take(40) {
+            assert_eq!(first.step(command), second.step(command));
+        }
+        assert_eq!(first.objects[PLAYER].pos(), second.objects[PLAYER].pos());
+        assert_eq!(first.game.turns, second.game.turns);
+    }
+}
@@ -0,0 +1,231 @@
+use std::cmp;
+use std::collections::{HashMap, HashSet, VecDeque};
+use rand::Rng;
+use rand::distributions::Weighted;
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::make_map::pick_weighted;
+
+pub mod rooms;
+pub mod bsp;
+pub mod caves;
+
+use rooms::RoomsGenerator;
+use bsp::BspGenerator;
+use caves::CaveGenerator;
+
+/// something that can dig out the floor plan of a level
+pub trait MapGenerator {
+    /// `None` when no level that fits the settings turned up
+    fn generate(&self, rng: &mut GameRng) -> Option<Layout>;
+}
+
+/// pick how a level of this theme is laid out, from the generators it allows
+pub fn choose_generator(theme: &ThemeDef, rng: &mut GameRng) -> Box<dyn MapGenerator> {
+    let generators = &mut theme
+        .generators
+        .iter()
+        .map(|generator| Weighted { weight: generator.weight, item: generator.kind })
+        .collect::<Vec<_>>();
+    match pick_weighted(generators, rng) {
+        GeneratorKind::Rooms { max_rooms, room_min_size, room_max_size } => Box::new(RoomsGenerator {
+            max_rooms,
+            room_min_size,
+            room_max_size,
+        }),
+        GeneratorKind::Bsp { min_leaf, max_leaf, min_room, loop_chance } => Box::new(BspGenerator {
+            min_leaf,
+            max_leaf,
+            min_room,
+            loop_chance,
+        }),
+        GeneratorKind::Caves { wall_chance, smoothing_passes, min_floor } => Box::new(CaveGenerator {
+            wall_chance,
+            smoothing_passes,
+            min_floor,
+        }),
+    }
+}
+
+/// plain rooms, for the levels of a theme whose own generators keep failing
+pub fn fallback_generator() -> Box<dyn MapGenerator> {
+    Box::new(RoomsGenerator {
+        max_rooms: FALLBACK_MAX_ROOMS,
+        room_min_size: FALLBACK_ROOM_MIN_SIZE,
+        room_max_size: FALLBACK_ROOM_MAX_SIZE,
+    })
+}
+
+/// a map with nothing but walls
+pub fn solid_map() -> Map {
+    vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize]
+}
+
+pub fn create_room(room: Rect, map: &mut Map) {
+    // go through the tiles in the rectangle and make them passable
+    for x in (room.x1 + 1)..room.x2 {
+        for y in (room.y1 + 1)..room.y2 {
+            map[x as usize][y as usize] = Tile::empty();
+        }
+    }
+}
+
+/// put doors, some of them locked, where tunnels come into the rooms through a gap
+/// in the wall just one tile wide
+pub fn place_doors(rooms: &[Rect], map: &mut Map, rng: &mut GameRng) {
+    let floor = |map: &Map, x: i32, y: i32| map[x as usize][y as usize].kind == TileKind::Floor;
+    let wall = |map: &Map, x: i32, y: i32| map[x as usize][y as usize].kind == TileKind::Wall;
+    for room in rooms {
+        // the walls around the room, without the corners
+        let top_and_bottom = ((room.x1 + 1)..room.x2).flat_map(|x| vec![(x, room.y1, true), (x, room.y2, true)]);
+        let sides = ((room.y1 + 1)..room.y2).flat_map(|y| vec![(room.x1, y, false), (room.x2, y, false)]);
+        for (x, y, horizontal) in top_and_bottom.chain(sides) {
+            // floor never touches the border of the map, so its neighbours are all on the map
+            if !floor(map, x, y) {
+                continue;
+            }
+            let doorway = if horizontal {
+                wall(map, x - 1, y) && wall(map, x + 1, y) && floor(map, x, y - 1) && floor(map, x, y + 1)
+            } else {
+                wall(map, x, y - 1) && wall(map, x, y + 1) && floor(map, x - 1, y) && floor(map, x + 1, y)
+            };
+            if doorway && rng.gen::<f32>() < DOOR_CHANCE {
+                map[x as usize][y as usize] = Tile::door(rng.gen::<f32>() < LOCKED_DOOR_CHANCE);
+            }
+        }
+    }
+}
+
+/// an L-shaped tunnel between two points, bending one way or the other at random
+pub fn create_tunnel(from: (i32, i32), to: (i32, i32), rng: &mut GameRng, map: &mut Map) {
+    let ((prev_x, prev_y), (new_x, new_y)) = (from, to);
+    // toss a coin (random bool value -- either true or false)
+    if rng.gen() {
+        // first move horizontally, then vertically
+        create_h_tunnel(prev_x, new_x, prev_y, map);
+        create_v_tunnel(prev_y, new_y, new_x, map);
+    } else {
+        // first move vertically, then horizontally
+        create_v_tunnel(prev_y, new_y, prev_x, map);
+        create_h_tunnel(prev_x, new_x, new_y, map);
+    }
+}
+
+fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
+    // horizontal tunnel. `min()` and `max()` are used in case `x1 > x2`
+    for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
+
+fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
+    // vertical tunnel
+    for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
+
+/// the eight tiles around a tile; you can walk diagonally too
+pub const NEIGHBOURS: [(i32, i32); 8] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+
+/// how many steps it takes to walk from `from` to every tile of the map, keeping out of
+/// harm's way; `None` for walls, harmful hazards and floor that can't be reached
+pub fn walking_distances(map: &Map, from: (i32, i32)) -> Vec<Vec<Option<u32>>> {
+    let mut distances = vec![vec![None; map[0].len()]; map.len()];
+    distances[from.0 as usize][from.1 as usize] = Some(0);
+    let mut queue = VecDeque::from(vec![from]);
+    while let Some((x, y)) = queue.pop_front() {
+        let steps = distances[x as usize][y as usize].unwrap();
+        for (dx, dy) in NEIGHBOURS.iter() {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= map.len() as i32 || ny >= map[0].len() as i32 {
+                continue;
+            }
+            let (ux, uy) = (nx as usize, ny as usize);
+            if map[ux][uy].safe() && distances[ux][uy].is_none() {
+                distances[ux][uy] = Some(steps + 1);
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+    distances
+}
+
+/// the floor tile that takes the longest walk to get to from `from`
+pub fn farthest_floor(map: &Map, from: (i32, i32)) -> (i32, i32) {
+    let distances = walking_distances(map, from);
+    let mut farthest = (from, 0);
+    for (x, column) in distances.iter().enumerate() {
+        for (y, steps) in column.iter().enumerate() {
+            if let Some(steps) = *steps {
+                if steps > farthest.1 {
+                    farthest = ((x as i32, y as i32), steps);
+                }
+            }
+        }
+    }
+    farthest.0
+}
+
+/// split the floor into the groups of tiles you can walk between
+pub fn floor_regions(map: &Map) -> Vec<Region> {
+    let mut seen = vec![vec![false; map[0].len()]; map.len()];
+    let mut regions = vec![];
+    for x in 0..map.len() {
+        for y in 0..map[x].len() {
+            if !map[x][y].safe() || seen[x][y] {
+                continue;
+            }
+            // flood the region from here, marking it off as it goes
+            seen[x][y] = true;
+            let mut region = vec![(x as i32, y as i32)];
+            let mut next = 0;
+            while let Some(&(rx, ry)) = region.get(next) {
+                next += 1;
+                for (dx, dy) in NEIGHBOURS.iter() {
+                    let (nx, ny) = (rx + dx, ry + dy);
+                    if nx < 0 || ny < 0 || nx >= map.len() as i32 || ny >= map[0].len() as i32 {
+                        continue;
+                    }
+                    let (ux, uy) = (nx as usize, ny as usize);
+                    if map[ux][uy].safe() && !seen[ux][uy] {
+                        seen[ux][uy] = true;
+                        region.push((nx, ny));
+                    }
+                }
+            }
+            // column by column, so the same seed still digs the same caves
+            region.sort();
+            regions.push(region);
+        }
+    }
+    regions
+}
+
+/// the shortest tunnel from any of the `from` tiles to floor elsewhere, digging around
+/// the `avoid` tiles and keeping off the border of the map; false if there is no way
+pub fn dig_to_floor(from: &[(i32, i32)], avoid: &HashSet<(i32, i32)>, map: &mut Map) -> bool {
+    let sources: HashSet<(i32, i32)> = from.iter().cloned().collect();
+    let mut came_from = HashMap::new();
+    let mut queue: VecDeque<(i32, i32)> = from.iter().cloned().collect();
+    while let Some((x, y)) = queue.pop_front() {
+        if !sources.contains(&(x, y)) && map[x as usize][y as usize].passable() {
+            // found floor: dig back to where we started
+            let mut pos = (x, y);
+            while let Some(&previous) = came_from.get(&pos) {
+                map[pos.0 as usize][pos.1 as usize] = Tile::empty();
+                pos = previous;
+            }
+            return true;
+        }
+        for next in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+            let inside = next.0 > 0 && next.1 > 0 && next.0 < MAP_WIDTH - 1 && next.1 < MAP_HEIGHT - 1;
+            if inside && !sources.contains(&next) && !avoid.contains(&next) && !came_from.contains_key(&next) {
+                came_from.insert(next, (x, y));
+                queue.push_back(next);
+            }
+        }
+    }
+    false
+}
@@ -0,0 +1,128 @@
+use rand::Rng;
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::generators::*;
+
+/// binary space partition: cut the map in two, then cut each half again, and so on;
+/// every piece left gets a room, and the two halves of every cut are joined by a tunnel.
+/// a few extra tunnels between neighbouring rooms make loops
+pub struct BspGenerator {
+    /// pieces are never cut smaller than this
+    pub min_leaf: i32,
+    /// pieces bigger than this are always cut, smaller ones only sometimes
+    pub max_leaf: i32,
+    pub min_room: i32,
+    pub loop_chance: f32,
+}
+
+impl MapGenerator for BspGenerator {
+    fn generate(&self, rng: &mut GameRng) -> Option<Layout> {
+        let mut map = solid_map();
+        let mut rooms = vec![];
+        // leave the last column and row alone, so the border of the map is always wall
+        let whole_map = Rect::new(0, 0, MAP_WIDTH - 1, MAP_HEIGHT - 1);
+        self.split(whole_map, rng, &mut map, &mut rooms);
+        if rooms.is_empty() {
+            return None;
+        }
+
+        // extra tunnels from some rooms to the closest other room
+        for i in 0..rooms.len() {
+            if rng.gen::<f32>() < self.loop_chance {
+                let closest = (0..rooms.len())
+                    .filter(|&j| j != i)
+                    .min_by_key(|&j| distance(rooms[i].center(), rooms[j].center()));
+                if let Some(j) = closest {
+                    create_tunnel(rooms[i].center(), rooms[j].center(), rng, &mut map);
+                }
+            }
+        }
+
+        place_doors(&rooms, &mut map, rng);
+
+        // start in a random room, and put the stairs in the one farthest away from it
+        let start = rooms[rng.gen_range(0, rooms.len())].center();
+        let stairs = rooms
+            .iter()
+            .map(|room| room.center())
+            .max_by_key(|&center| distance(start, center))
+            .unwrap();
+        Some(Layout {
+            map,
+            regions: rooms.iter().map(Rect::floor).collect(),
+            start,
+            stairs,
+        })
+    }
+}
+
+impl BspGenerator {
+    /// dig the rooms of this piece of the map into `rooms`, joined together;
+    /// returns the range of `rooms` they ended up in
+    fn split(&self, area: Rect, rng: &mut GameRng, map: &mut Map, rooms: &mut Vec<Rect>) -> std::ops::Range<usize> {
+        let (width, height) = (area.x2 - area.x1, area.y2 - area.y1);
+        let can_cut_x = width >= self.min_leaf * 2;
+        let can_cut_y = height >= self.min_leaf * 2;
+        let too_big = width > self.max_leaf || height > self.max_leaf;
+        // small enough pieces are sometimes left whole, for some variety in room sizes
+        if !(can_cut_x || can_cut_y) || (!too_big && rng.gen_weighted_bool(3)) {
+            return self.dig_room(area, rng, map, rooms);
+        }
+
+        // cut across the longer side, or either way when the piece is about square
+        let cut_x = if !can_cut_y {
+            true
+        } else if !can_cut_x {
+            false
+        } else if width as f32 > height as f32 * 1.25 {
+            true
+        } else if height as f32 > width as f32 * 1.25 {
+            false
+        } else {
+            rng.gen()
+        };
+        let (first, second) = if cut_x {
+            let cut = rng.gen_range(self.min_leaf, width - self.min_leaf + 1);
+            (
+                Rect::new(area.x1, area.y1, cut, height),
+                Rect::new(area.x1 + cut, area.y1, width - cut, height),
+            )
+        } else {
+            let cut = rng.gen_range(self.min_leaf, height - self.min_leaf + 1);
+            (
+                Rect::new(area.x1, area.y1, width, cut),
+                Rect::new(area.x1, area.y1 + cut, width, height - cut),
+            )
+        };
+        let first = self.split(first, rng, map, rooms);
+        let second = self.split(second, rng, map, rooms);
+
+        // join the two halves through the two rooms closest to each other
+        let (a, b) = first
+            .clone()
+            .flat_map(|a| second.clone().map(move |b| (a, b)))
+            .min_by_key(|&(a, b)| distance(rooms[a].center(), rooms[b].center()))
+            .unwrap();
+        create_tunnel(rooms[a].center(), rooms[b].center(), rng, map);
+        first.start..second.end
+    }
+
+    /// a room of random size somewhere inside the piece
+    fn dig_room(&self, area: Rect, rng: &mut GameRng, map: &mut Map, rooms: &mut Vec<Rect>) -> std::ops::Range<usize> {
+        let (width, height) = (area.x2 - area.x1, area.y2 - area.y1);
+        let w = rng.gen_range(self.min_room.min(width), width + 1);
+        let h = rng.gen_range(self.min_room.min(height), height + 1);
+        let x = rng.gen_range(area.x1, area.x2 - w + 1);
+        let y = rng.gen_range(area.y1, area.y2 - h + 1);
+        let room = Rect::new(x, y, w, h);
+        create_room(room, map);
+        rooms.push(room);
+        rooms.len() - 1..rooms.len()
+    }
+}
+
+/// squared distance, good enough to compare which is closer
+fn distance((x1, y1): (i32, i32), (x2, y2): (i32, i32)) -> i32 {
+    (x1 - x2).pow(2) + (y1 - y2).pow(2)
+}
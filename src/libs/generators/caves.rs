@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use rand::Rng;
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::generators::*;
+
+/// caves grown with a cellular automaton: fill the map with walls at random, then
+/// smooth it out a few times so walls gather together and open spaces join up.
+/// only the biggest cave is kept, every smaller pocket is filled back in
+pub struct CaveGenerator {
+    pub wall_chance: f32,
+    pub smoothing_passes: u32,
+    /// caves with less floor than this are dug again, CAVE_TRIES times at most
+    pub min_floor: usize,
+}
+
+impl MapGenerator for CaveGenerator {
+    fn generate(&self, rng: &mut GameRng) -> Option<Layout> {
+        for _ in 0..CAVE_TRIES {
+            let mut map = self.random_fill(rng);
+            for _ in 0..self.smoothing_passes {
+                map = smooth(&map);
+            }
+
+            // keep the biggest cave only
+            let mut caves = floor_regions(&map);
+            caves.sort_by_key(|cave| cave.len());
+            let cave = match caves.pop() {
+                Some(cave) if cave.len() >= self.min_floor => cave,
+                _ => continue,
+            };
+            for &(x, y) in caves.iter().flatten() {
+                map[x as usize][y as usize] = Tile::wall();
+            }
+
+            // start anywhere, and put the stairs as far a walk away as it gets
+            let start = cave[rng.gen_range(0, cave.len())];
+            let stairs = farthest_floor(&map, start);
+            return Some(Layout {
+                regions: share_out(cave),
+                map,
+                start,
+                stairs,
+            });
+        }
+        None
+    }
+}
+
+impl CaveGenerator {
+    /// walls and floor at random, except for the border of the map which is always wall
+    fn random_fill(&self, rng: &mut GameRng) -> Map {
+        let mut map = solid_map();
+        for x in 1..MAP_WIDTH - 1 {
+            for y in 1..MAP_HEIGHT - 1 {
+                if rng.gen::<f32>() >= self.wall_chance {
+                    map[x as usize][y as usize] = Tile::empty();
+                }
+            }
+        }
+        map
+    }
+}
+
+/// a tile becomes wall when most of the tiles around it (itself included) are walls,
+/// and floor otherwise
+fn smooth(map: &Map) -> Map {
+    let mut smoothed = solid_map();
+    for x in 1..MAP_WIDTH - 1 {
+        for y in 1..MAP_HEIGHT - 1 {
+            let walls = NEIGHBOURS
+                .iter()
+                .chain(&[(0, 0)])
+                .filter(|(dx, dy)| !map[(x + dx) as usize][(y + dy) as usize].passable())
+                .count();
+            if walls < 5 {
+                smoothed[x as usize][y as usize] = Tile::empty();
+            }
+        }
+    }
+    smoothed
+}
+
+/// a cave is one big region; cut it into squares so monsters and items get spread
+/// around it like they are around rooms. scraps too small to be a room join no region
+fn share_out(cave: Region) -> Vec<Region> {
+    let mut squares: BTreeMap<(i32, i32), Region> = BTreeMap::new();
+    for (x, y) in cave {
+        squares
+            .entry((x / CAVE_REGION_SIZE, y / CAVE_REGION_SIZE))
+            .or_default()
+            .push((x, y));
+    }
+    squares
+        .into_values()
+        .filter(|square| square.len() >= CAVE_MIN_REGION)
+        .collect()
+}
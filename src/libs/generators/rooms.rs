@@ -0,0 +1,54 @@
+use rand::Rng;
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::generators::*;
+
+/// the classic plan: random rooms that don't overlap, each one joined to the
+/// room dug before it
+pub struct RoomsGenerator {
+    pub max_rooms: i32,
+    pub room_min_size: i32,
+    pub room_max_size: i32,
+}
+
+impl MapGenerator for RoomsGenerator {
+    fn generate(&self, rng: &mut GameRng) -> Option<Layout> {
+        let mut map = solid_map();
+        let mut rooms: Vec<Rect> = vec![];
+
+        for _ in 0..self.max_rooms {
+            // random width and height
+            let w = rng.gen_range(self.room_min_size, self.room_max_size + 1);
+            let h = rng.gen_range(self.room_min_size, self.room_max_size + 1);
+            // random position without going out of the boundaries of the map
+            let x = rng.gen_range(0, MAP_WIDTH - w);
+            let y = rng.gen_range(0, MAP_HEIGHT - h);
+            let new_room = Rect::new(x, y, w, h);
+            let failed = rooms
+                .iter()
+                .any(|other_room| new_room.intersects_with(other_room));
+            if !failed {
+                // this means there are no intersections, so this room is valid
+
+                // "paint" it to the map's tiles
+                create_room(new_room, &mut map);
+
+                // all rooms after the first:
+                // connect it to the previous room with a tunnel
+                if let Some(prev_room) = rooms.last() {
+                    create_tunnel(prev_room.center(), new_room.center(), rng, &mut map);
+                }
+                rooms.push(new_room);
+            }
+        }
+        place_doors(&rooms, &mut map, rng);
+        // the player starts in the first room, the stairs are in the last one
+        Some(Layout {
+            start: rooms[0].center(),
+            stairs: rooms[rooms.len() - 1].center(),
+            map,
+            regions: rooms.iter().map(Rect::floor).collect(),
+        })
+    }
+}
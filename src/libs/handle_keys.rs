@@ -1,82 +1,69 @@
+use rand::Rng;
 use tcod::colors::*;
-
-use crate::predefs::structs::{Tcod, Object, Game};
+use tcod::map::Map as FovMap;
 
 use crate::predefs::structs::*;
-use PlayerAction::*;
+use KeyAction::*;
+use PlayerCommand::*;
 use crate::predefs::constants::*;
 use crate::libs::make_map::*;
 use crate::libs::ai::*;
 use crate::libs::menu::*;
+use crate::libs::render::draw_map;
+use crate::libs::export::export_canvas;
 
-pub fn handle_keys(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) -> PlayerAction {
-    use tcod::input::Key;
-    use tcod::input::KeyCode::*;
-
+pub fn handle_keys(ui: &mut Ui, state: &GameState) -> KeyAction {
+    let GameState { game, objects, .. } = state;
     let player_alive = objects[PLAYER].alive;
-    match (tcod.key, tcod.key.text(), player_alive) {
-        (Key {
-            code: Enter,
-            alt: true,
-            ..
-        },
-        _,
-        _,)
-         => {
-            // Alt+Enter: toggle fullscreen
-            let fullscreen = tcod.root.is_fullscreen();
-            tcod.root.set_fullscreen(!fullscreen);
-            DidntTakeTurn
+    // what the key does comes from the keymap file
+    match (ui.keymap.command(ui.key), player_alive) {
+        (Some(KeyCommand::Fullscreen), _) => {
+            // toggle fullscreen (Alt+Enter by default)
+            ui.backend.toggle_fullscreen();
+            Ignored
         }
-        (Key { code: Escape, .. },_,_,) => return Exit, // exit game
-        (Key { code: Text, .. }, "g", true) => {
-            // pick up an item
-            let item_id = objects
-                .iter()
-                .position(|object| object.pos() == objects[PLAYER].pos() && object.item.is_some());
-            if let Some(item_id) = item_id {
-                pick_item_up(item_id, game, objects);
-            }
-            DidntTakeTurn
-        },
-        (Key { code: Text, .. }, "i", true) => {
+        (Some(KeyCommand::Exit), _) => Exit, // exit game
+        (Some(KeyCommand::Screenshot), _) => {
+            // what is on screen right now
+            let screen = ui.root.clone();
+            export(&screen, "screenshot", ui);
+            Ignored
+        }
+        (Some(KeyCommand::ExportMap), _) => {
+            // all of the level the player knows about, not just what fits on screen
+            let mut map = Canvas::new(MAP_WIDTH, MAP_HEIGHT);
+            draw_map(&mut map, state, (0, 0));
+            export(&map, "map", ui);
+            Ignored
+        }
+        (Some(KeyCommand::PickUp), true) => Command(PickUp), // pick up an item
+        (Some(KeyCommand::Inventory), true) => {
             // show the inventory
             let inventory_index = inventory_menu(
                 &game.inventory,
                 "Press the key next to an item to use it, or any other to cancel.\n",
-                &mut tcod.root);
-            if let Some(inventory_index) = inventory_index {
-                use_item(inventory_index, tcod, game, objects);
-            }
-            DidntTakeTurn
+                ui);
+            inventory_index.map_or(Ignored, |index| Command(UseItem(index)))
         },
-        (Key { code: Text, .. }, "d", true) => {
+        (Some(KeyCommand::Drop), true) => {
             // show the inventory; if an item is selected, drop it
             let inventory_index = inventory_menu(
                 &game.inventory,
                 "Press the key next to an item to drop it, or any other to cancel.\n'",
-                &mut tcod.root,
+                ui,
             );
-            if let Some(inventory_index) = inventory_index {
-                drop_item(inventory_index, game, objects);
-            }
-            DidntTakeTurn
+            inventory_index.map_or(Ignored, |index| Command(DropItem(index)))
         },
-        (Key { code: Text, .. }, "<", true) => {
-            // go down stairs, if the player is on them
-            let player_on_stairs = objects
-                .iter()
-                .any(|object| object.pos() == objects[PLAYER].pos() && object.name == "stairs");
-            if player_on_stairs {
-                next_level(tcod, game, objects);
-            }
-            DidntTakeTurn
-        }
-        (Key { code: Text, .. }, "c", true) => {
+        (Some(KeyCommand::Descend), true) => Command(Descend), // go down stairs
+        (Some(KeyCommand::Ascend), true) => Command(Ascend),
+        (Some(KeyCommand::CloseDoor), true) => Command(CloseDoor),
+        (Some(KeyCommand::Search), true) => Command(Search),
+        (Some(KeyCommand::Disarm), true) => Command(Disarm),
+        (Some(KeyCommand::Character), true) => {
             // show character information
             let player = &objects[PLAYER];
             let level = player.level;
-            let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
+            let level_up_xp = level_up_xp(player);
             if let Some(fighter) = player.fighter.as_ref() {
                 let msg = format!(
                     "Character information
@@ -87,56 +74,43 @@ pub fn handle_keys(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>)
         
         Maximum HP: {}
         Attack: {}
-        Defense: {}",
-                    level, fighter.xp, level_up_xp, player.max_hp(game), player.power(game), player.defense(game)
+        Defense: {}
+
+        Seed: {}",
+                    level, fighter.xp, level_up_xp, player.max_hp(game), player.power(game), player.defense(game),
+                    game.seed.map_or("unknown".into(), |seed| seed.to_string())
                 );
-                msgbox(&msg, CHARACTER_SCREEN_WIDTH, &mut tcod.root);
+                msgbox(&msg, CHARACTER_SCREEN_WIDTH, ui);
             }
         
-            DidntTakeTurn
+            Ignored
         }
         // movement keys
-        (Key { code: Up, .. }, _, true) | (Key { code: NumPad8, .. }, _, true) => {
-            player_move_or_attack(0, -1, game, objects);
-            TookTurn
-        }
-        (Key { code: Down, .. }, _, true) | (Key { code: NumPad2, .. }, _, true) => {
-            player_move_or_attack(0, 1, game, objects);
-            TookTurn
-        }
-        (Key { code: Left, .. }, _, true) | (Key { code: NumPad4, .. }, _, true) => {
-            player_move_or_attack(-1, 0, game, objects);
-            TookTurn
-        }
-        (Key { code: Right, .. }, _, true) | (Key { code: NumPad6, .. }, _, true) => {
-            player_move_or_attack(1, 0, game, objects);
-            TookTurn
-        }
-        (Key { code: Home, .. }, _, true) | (Key { code: NumPad7, .. }, _, true) => {
-            player_move_or_attack(-1, -1, game, objects);
-            TookTurn
-        }
-        (Key { code: PageUp, .. }, _, true) | (Key { code: NumPad9, .. }, _, true) => {
-            player_move_or_attack(1, -1, game, objects);
-            TookTurn
-        }
-        (Key { code: End, .. }, _, true) | (Key { code: NumPad1, .. }, _, true) => {
-            player_move_or_attack(-1, 1, game, objects);
-            TookTurn
-        }
-        (Key { code: PageDown, .. }, _, true) | (Key { code: NumPad3, .. }, _, true) => {
-            player_move_or_attack(1, 1, game, objects);
-            TookTurn
-        }
-        (Key { code: NumPad5, .. }, _, true) => {
-            TookTurn // do nothing, i.e. wait for the monster to come to you
+        (Some(KeyCommand::MoveUp), true) => Command(Move { dx: 0, dy: -1 }),
+        (Some(KeyCommand::MoveDown), true) => Command(Move { dx: 0, dy: 1 }),
+        (Some(KeyCommand::MoveLeft), true) => Command(Move { dx: -1, dy: 0 }),
+        (Some(KeyCommand::MoveRight), true) => Command(Move { dx: 1, dy: 0 }),
+        (Some(KeyCommand::MoveUpLeft), true) => Command(Move { dx: -1, dy: -1 }),
+        (Some(KeyCommand::MoveUpRight), true) => Command(Move { dx: 1, dy: -1 }),
+        (Some(KeyCommand::MoveDownLeft), true) => Command(Move { dx: -1, dy: 1 }),
+        (Some(KeyCommand::MoveDownRight), true) => Command(Move { dx: 1, dy: 1 }),
+        (Some(KeyCommand::Wait), true) => {
+            Command(Wait) // do nothing, i.e. wait for the monster to come to you
         }
 
-        _ => DidntTakeTurn
+        _ => Ignored
     }
 }
 
-pub fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [Object]) {
+fn export(canvas: &Canvas, name: &str, ui: &mut Ui) {
+    let text = match export_canvas(canvas, name) {
+        Ok(path) => format!("\nSaved to {}.png, .ans and .html\n", path.display()),
+        Err(e) => format!("\nCould not export the screen: {}\n", e),
+    };
+    msgbox(&text, SCREENSHOT_WIDTH, ui);
+}
+
+pub fn player_move_or_attack(dx: i32, dy: i32, fov: &FovMap, game: &mut Game, objects: &mut Objects) {
     // the coordinates the player is moving to/attacking
     let x = objects[PLAYER].x + dx;
     let y = objects[PLAYER].y + dy;
@@ -144,20 +118,35 @@ pub fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [O
     // try to find an attackable object there
     let target_id = objects
     .iter()
-    .position(|object| object.fighter.is_some() && object.pos() == (x, y));
+    .find(|(_, object)| object.fighter.is_some() && object.pos() == (x, y))
+    .map(|(id, _)| id);
 
     
     // attack if target found, move otherwise
     match target_id {
         Some(target_id) => {
-            let (player, target) = mut_two(PLAYER, target_id, objects);
+            let (player, target) = objects.pair_mut(PLAYER, target_id);
             player.attack(target, game);
         }
         None => {
-            move_by(PLAYER, dx, dy, &game.map, objects);
+            if let TileKind::Door { open: false, locked: true } = game.map[x as usize][y as usize].kind {
+                force_door(x, y, game);
+            } else {
+                move_by(PLAYER, dx, dy, fov, game, objects);
+            }
         }
     }
 }
+/// throw yourself against a locked door, which sometimes breaks the lock
+fn force_door(x: i32, y: i32, game: &mut Game) {
+    if game.rng.gen::<f32>() < FORCE_DOOR_CHANCE {
+        game.map[x as usize][y as usize].kind = TileKind::Door { open: true, locked: false };
+        game.messages.add("You break the lock and force the door open.", WHITE);
+    } else {
+        game.messages.add("The door is locked. You push against it, but it holds.", WHITE);
+    }
+}
+
 pub fn player_death(player: &mut Object, game: &mut Game) {
     // the game ended!
     game.messages.add("You died!", RED);
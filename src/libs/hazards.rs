@@ -0,0 +1,163 @@
+use std::collections::HashSet;
+use rand::Rng;
+use rand::distributions::{IndependentSample, Weighted, WeightedChoice};
+
+use tcod::colors::*;
+use tcod::map::Map as FovMap;
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::make_map::from_dungeon_level;
+use crate::libs::generators::NEIGHBOURS;
+use crate::libs::validate::check_map;
+
+/// pools of water, lava and chasms on the floor, more of them and nastier the deeper
+/// you go, unless the theme has pools of its own. a pool that would cut off any of the
+/// level from the start is filled back in
+pub fn place_hazards(
+    map: &mut Map,
+    objects: &Objects,
+    start: (i32, i32),
+    stairs: (i32, i32),
+    level: u32,
+    theme: Option<HazardsDef>,
+    rng: &mut GameRng,
+) {
+    let mut max_pools = from_dungeon_level(
+        &[
+            Transition { level: 1, value: 2 },
+            Transition { level: 4, value: 3 },
+            Transition { level: 7, value: 4 },
+        ],
+        level,
+    );
+    let mut water_chance = 60;
+    let mut lava_chance = from_dungeon_level(
+        &[
+            Transition { level: 3, value: 15 },
+            Transition { level: 6, value: 35 },
+        ],
+        level,
+    );
+    let mut chasm_chance = from_dungeon_level(
+        &[
+            Transition { level: 2, value: 15 },
+            Transition { level: 5, value: 25 },
+        ],
+        level,
+    );
+    if let Some(hazards) = theme {
+        max_pools = hazards.max_pools;
+        water_chance = hazards.water;
+        lava_chance = hazards.lava;
+        chasm_chance = hazards.chasm;
+    }
+    // nothing goes where the player starts, on the stairs, or under what is already there
+    let mut keep: HashSet<(i32, i32)> = objects
+        .iter()
+        .filter(|&(id, _)| id != PLAYER)
+        .map(|(_, object)| object.pos())
+        .collect();
+    keep.insert(start);
+    keep.insert(stairs);
+
+    for _ in 0..rng.gen_range(0, max_pools + 1) {
+        let hazards = &mut [
+            // water pools are deep in the middle and shallow around the edges
+            Weighted { weight: water_chance, item: Hazard::DeepWater },
+            Weighted { weight: lava_chance, item: Hazard::Lava },
+            Weighted { weight: chasm_chance, item: Hazard::Chasm },
+        ];
+        let hazard = WeightedChoice::new(hazards).ind_sample(rng);
+        let pool = grow_pool(map, &keep, rng);
+
+        let before: Vec<Tile> = pool.iter().map(|&(x, y)| map[x as usize][y as usize]).collect();
+        for &(x, y) in &pool {
+            let shore = NEIGHBOURS.iter().any(|(dx, dy)| !pool.contains(&(x + dx, y + dy)));
+            let hazard = match hazard {
+                Hazard::DeepWater if shore => Hazard::ShallowWater,
+                hazard => hazard,
+            };
+            map[x as usize][y as usize] = Tile::hazard(hazard);
+        }
+        if check_map(map, start, stairs).is_err() {
+            for (&(x, y), &tile) in pool.iter().zip(&before) {
+                map[x as usize][y as usize] = tile;
+            }
+        }
+    }
+}
+
+/// a blob of floor tiles, grown a tile at a time from a random spot
+fn grow_pool(map: &Map, keep: &HashSet<(i32, i32)>, rng: &mut GameRng) -> Vec<(i32, i32)> {
+    let free = |(x, y): (i32, i32)| map[x as usize][y as usize].kind == TileKind::Floor && !keep.contains(&(x, y));
+    let floor: Vec<(i32, i32)> = (0..MAP_WIDTH)
+        .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)))
+        .filter(|&pos| free(pos))
+        .collect();
+    if floor.is_empty() {
+        return vec![];
+    }
+    let size = rng.gen_range(HAZARD_MIN_SIZE, HAZARD_MAX_SIZE + 1);
+    let mut pool = vec![floor[rng.gen_range(0, floor.len())]];
+    // give up after a while, in case the pool is boxed in
+    for _ in 0..size * 4 {
+        if pool.len() >= size {
+            break;
+        }
+        let (x, y) = pool[rng.gen_range(0, pool.len())];
+        let (dx, dy) = [(-1, 0), (1, 0), (0, -1), (0, 1)][rng.gen_range(0, 4)];
+        let next = (x + dx, y + dy);
+        if free(next) && !pool.contains(&next) {
+            pool.push(next);
+        }
+    }
+    pool
+}
+
+/// lava burns whatever stands in it, and monsters that stumble into a chasm fall to
+/// the level below, out of the game
+pub fn hazards_take_turn(fov: &FovMap, game: &mut Game, objects: &mut Objects) {
+    for id in objects.ids() {
+        let object = &objects[id];
+        if object.fighter.is_none() {
+            continue;
+        }
+        let (x, y) = object.pos();
+        match object.hazard_in(&game.map[x as usize][y as usize]) {
+            Some(Hazard::Lava) => {
+                if id == PLAYER {
+                    game.messages.add(format!("The lava burns you for {} hit points.", LAVA_DAMAGE), ORANGE);
+                } else if fov.is_in_fov(x, y) {
+                    game.messages.add(
+                        format!("The {} is burned by the lava for {} hit points.", object.name, LAVA_DAMAGE),
+                        ORANGE,
+                    );
+                }
+                objects[id].take_damage(LAVA_DAMAGE, game);
+            }
+            Some(Hazard::Chasm) if id != PLAYER => {
+                if fov.is_in_fov(x, y) {
+                    game.messages.add(format!("The {} falls into the chasm!", object.name), ORANGE);
+                }
+                objects.remove(id);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// a stroke through deep water: something the player isn't holding on to may slip away
+pub fn swim(game: &mut Game) {
+    if game.rng.gen::<f32>() >= LOSE_GRIP_CHANCE {
+        return;
+    }
+    let loose: Vec<usize> = (0..game.inventory.len())
+        .filter(|&index| !game.inventory[index].equipment.is_some_and(|e| e.equipped))
+        .collect();
+    if loose.is_empty() {
+        return;
+    }
+    let item = game.inventory.remove(loose[game.rng.gen_range(0, loose.len())]);
+    game.messages.add(format!("Your {} slips away and sinks.", item.name), RED);
+}
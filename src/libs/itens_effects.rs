@@ -1,17 +1,14 @@
 use tcod::colors::*;
 use crate::predefs::constants::*;
 use crate::predefs::structs::*;
-use crate::libs::make_map::{closest_monster, target_tile, target_monster};
+use crate::libs::make_map::{closest_monster, monster_at};
 use crate::libs::menu::{get_equipped_in_slot};
+use crate::libs::ai::confuse;
 
-pub fn cast_heal(
-    _inventory_id: usize,
-    _tcod: &mut Tcod,
-    game: &mut Game,
-    objects: &mut [Object],
-) -> UseResult {
+pub fn cast_heal(amount: i32, state: &mut GameState) -> UseResult {
     // heal the player
-    let player = &mut objects[PLAYER];
+    let game = &mut state.game;
+    let player = &mut state.objects[PLAYER];
     if let Some(fighter) = player.fighter {
         if fighter.hp == player.max_hp(game) {  
             game.messages.add("You are already at full health.", RED);
@@ -19,31 +16,27 @@ pub fn cast_heal(
         }
         game.messages
             .add("Your wounds start to feel better!", LIGHT_VIOLET);
-        player.heal(HEAL_AMOUNT, game);  
+        player.heal(amount, game);  
         return UseResult::UsedUp;
     }
     UseResult::Cancelled
 }
 
-pub fn cast_lightning(
-    _inventory_id: usize,
-    tcod: &mut Tcod,
-    game: &mut Game,
-    objects: &mut [Object],
-) -> UseResult {
+pub fn cast_lightning(damage: i32, range: i32, state: &mut GameState) -> UseResult {
+    let GameState { game, objects, fov, .. } = state;
     // find closest enemy (inside a maximum range and damage it)
-    let monster_id = closest_monster(tcod, objects, LIGHTNING_RANGE);
+    let monster_id = closest_monster(fov, objects, range);
     if let Some(monster_id) = monster_id {
         // zap it!
         game.messages.add(
             format!(
                 "A lightning bolt strikes the {} with a loud thunder! \
                  The damage is {} hit points.",
-                objects[monster_id].name, LIGHTNING_DAMAGE
+                objects[monster_id].name, damage
             ),
             LIGHT_BLUE,
         );
-        if let Some(xp) = objects[monster_id].take_damage(LIGHTNING_DAMAGE, game) {
+        if let Some(xp) = objects[monster_id].take_damage(damage, game) {
             objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
         }
         UseResult::UsedUp
@@ -55,28 +48,12 @@ pub fn cast_lightning(
     }
 }
 
-pub fn cast_confusion(
-    _inventory_id: usize,
-    tcod: &mut Tcod,
-    game: &mut Game,
-    objects: &mut [Object],
-) -> UseResult {
-    // find closest enemy in-range and confuse it
-    // let monster_id = closest_monster(tcod, objects,CONFUSE_RANGE) ;
-    // ask the player for a target to confuse
-    game.messages.add(
-        "Left-click an enemy to confuse it, or right-click to cancel.",
-        LIGHT_CYAN,
-    );
-    let monster_id = target_monster(tcod, game, objects, Some(CONFUSE_RANGE as f32));
+pub fn cast_confusion(turns: i32, target: Option<(i32, i32)>, state: &mut GameState) -> UseResult {
+    let GameState { game, objects, .. } = state;
+    // the player was already asked for a target to confuse
+    let monster_id = target.and_then(|(x, y)| monster_at(x, y, objects));
     if let Some(monster_id) = monster_id {
-        let old_ai = objects[monster_id].ai.take().unwrap_or(Ai::Basic);
-        // replace the monster's AI with a "confused" one; after
-        // some turns it will restore the old AI
-        objects[monster_id].ai = Some(Ai::Confused {
-            previous_ai: Box::new(old_ai),
-            num_turns: CONFUSE_NUM_TURNS,
-        });
+        confuse(monster_id, turns, objects);
         game.messages.add(
             format!(
                 "The eyes of {} look vacant, as he starts to stumble around!",
@@ -93,39 +70,41 @@ pub fn cast_confusion(
     }
 }
 pub fn cast_fireball(
-    _inventory_id: usize,
-    tcod: &mut Tcod,
-    game: &mut Game,
-    objects: &mut [Object],
+    damage: i32,
+    radius: i32,
+    target: Option<(i32, i32)>,
+    state: &mut GameState,
 ) -> UseResult {
-    // ask the player for a target tile to throw a fireball at
-    game.messages.add(
-        "Left-click a target tile for the fireball, or right-click to cancel.",
-        LIGHT_CYAN,
-    );
-    let (x, y) = match target_tile(tcod, game, objects, None) {
+    let GameState { game, objects, .. } = state;
+    // the player was already asked for a target tile to throw a fireball at
+    let (x, y) = match target {
         Some(tile_pos) => tile_pos,
         None => return UseResult::Cancelled,
     };
     game.messages.add(
         format!(
             "The fireball explodes, burning everything within {} tiles!",
-            FIREBALL_RADIUS
+            radius
         ),
         ORANGE,
     );
     let mut xp_to_gain = 0;
 
-    for (id, obj) in objects.iter_mut().enumerate() {  
-        if obj.distance(x, y) <= FIREBALL_RADIUS as f32 && obj.fighter.is_some() {
+    for (id, obj) in objects.iter_mut() {  
+        if obj.distance(x, y) <= radius as f32 && obj.fighter.is_some() {
+            // water puts the flames out
+            if let TileKind::Hazard(Hazard::ShallowWater | Hazard::DeepWater) = game.map[obj.x as usize][obj.y as usize].kind {
+                game.messages.add(format!("The water around the {} puts out the flames.", obj.name), LIGHT_BLUE);
+                continue;
+            }
             game.messages.add(
                 format!(
                     "The {} gets burned for {} hit points.",
-                    obj.name, FIREBALL_DAMAGE
+                    obj.name, damage
                 ),
                 ORANGE,
             );
-            if let Some(xp) = obj.take_damage(FIREBALL_DAMAGE, game) {
+            if let Some(xp) = obj.take_damage(damage, game) {
                 if id != PLAYER {  
                     // Don't reward the player for burning themself!
                     xp_to_gain += xp;
@@ -138,13 +117,9 @@ pub fn cast_fireball(
     UseResult::UsedUp
 }
 
-pub fn toggle_equipment(
-    inventory_id: usize,
-    _tcod: &mut Tcod,
-    game: &mut Game,
-    _objects: &mut [Object],
-) -> UseResult {
-    
+pub fn toggle_equipment(inventory_id: usize, state: &mut GameState) -> UseResult {
+    let game = &mut state.game;
+
     let equipment = match game.inventory[inventory_id].equipment {
         Some(equipment) => equipment,
         None => return UseResult::Cancelled,
@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use serde::Deserialize;
+use tcod::input::KeyCode;
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::content::read_json;
+
+#[derive(Deserialize)]
+struct KeymapFile {
+    /// the preset used when none is asked for on the command line
+    preset: String,
+    presets: BTreeMap<String, BTreeMap<KeyCommand, Vec<String>>>,
+}
+
+/// every command a keymap has to bind, so nothing is out of reach
+const ALL_COMMANDS: &[KeyCommand] = &[
+    KeyCommand::MoveUp,
+    KeyCommand::MoveDown,
+    KeyCommand::MoveLeft,
+    KeyCommand::MoveRight,
+    KeyCommand::MoveUpLeft,
+    KeyCommand::MoveUpRight,
+    KeyCommand::MoveDownLeft,
+    KeyCommand::MoveDownRight,
+    KeyCommand::Wait,
+    KeyCommand::PickUp,
+    KeyCommand::Inventory,
+    KeyCommand::Drop,
+    KeyCommand::Descend,
+    KeyCommand::Ascend,
+    KeyCommand::CloseDoor,
+    KeyCommand::Search,
+    KeyCommand::Disarm,
+    KeyCommand::Character,
+    KeyCommand::Screenshot,
+    KeyCommand::ExportMap,
+    KeyCommand::Fullscreen,
+    KeyCommand::Exit,
+];
+
+/// the names keys have in the keymap file, besides single characters
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("Escape", KeyCode::Escape),
+    ("Backspace", KeyCode::Backspace),
+    ("Tab", KeyCode::Tab),
+    ("Enter", KeyCode::Enter),
+    ("Space", KeyCode::Spacebar),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("End", KeyCode::End),
+    ("Home", KeyCode::Home),
+    ("Up", KeyCode::Up),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Down", KeyCode::Down),
+    ("Insert", KeyCode::Insert),
+    ("Delete", KeyCode::Delete),
+    ("NumPad0", KeyCode::NumPad0),
+    ("NumPad1", KeyCode::NumPad1),
+    ("NumPad2", KeyCode::NumPad2),
+    ("NumPad3", KeyCode::NumPad3),
+    ("NumPad4", KeyCode::NumPad4),
+    ("NumPad5", KeyCode::NumPad5),
+    ("NumPad6", KeyCode::NumPad6),
+    ("NumPad7", KeyCode::NumPad7),
+    ("NumPad8", KeyCode::NumPad8),
+    ("NumPad9", KeyCode::NumPad9),
+    ("NumPadEnter", KeyCode::NumPadEnter),
+    ("F1", KeyCode::F1),
+    ("F2", KeyCode::F2),
+    ("F3", KeyCode::F3),
+    ("F4", KeyCode::F4),
+    ("F5", KeyCode::F5),
+    ("F6", KeyCode::F6),
+    ("F7", KeyCode::F7),
+    ("F8", KeyCode::F8),
+    ("F9", KeyCode::F9),
+    ("F10", KeyCode::F10),
+    ("F11", KeyCode::F11),
+    ("F12", KeyCode::F12),
+];
+
+/// read the keymap file and pick a preset: the one asked for, or the file's default
+pub fn load_keymap(preset: Option<&str>) -> Result<Keymap, Box<dyn Error>> {
+    let file: KeymapFile = read_json(KEYMAP_FILE)?;
+    let name = preset.unwrap_or(&file.preset);
+    let preset = file.presets.get(name).ok_or_else(|| {
+        let known: Vec<&str> = file.presets.keys().map(|name| name.as_str()).collect();
+        format!("{}: no preset \"{}\", there are: {}", KEYMAP_FILE, name, known.join(", "))
+    })?;
+    let keymap = build_keymap(preset).map_err(|e| format!("{}: preset \"{}\": {}", KEYMAP_FILE, name, e))?;
+    Ok(keymap)
+}
+
+fn build_keymap(preset: &BTreeMap<KeyCommand, Vec<String>>) -> Result<Keymap, String> {
+    let mut keymap = Keymap { bindings: vec![] };
+    for (&command, keys) in preset {
+        for key in keys {
+            let binding = parse_binding(key)?;
+            // the same key can't mean two things
+            if let Some((_, other)) = keymap.bindings.iter().find(|(bound, _)| *bound == binding) {
+                return Err(format!("\"{}\" is bound to both {} and {}", key, name(*other), name(command)));
+            }
+            keymap.bindings.push((binding, command));
+        }
+    }
+    if let Some(command) = ALL_COMMANDS.iter().find(|command| keymap.keys_for(**command).is_empty()) {
+        return Err(format!("no key is bound to {}", name(*command)));
+    }
+    Ok(keymap)
+}
+
+/// the command as it is written in the file, quoted
+fn name(command: KeyCommand) -> String {
+    serde_json::to_string(&command).unwrap_or_default()
+}
+
+/// "k", "<", "Up", "NumPad8", "alt+Enter", "ctrl+s"...
+fn parse_binding(spec: &str) -> Result<KeyBinding, String> {
+    // "+" on its own, or at the end as in "ctrl++", is the plus key itself
+    let (modifiers, name) = match spec.strip_suffix("++") {
+        _ if spec == "+" => ("", "+"),
+        Some(modifiers) => (modifiers, "+"),
+        None => spec.rsplit_once('+').unwrap_or(("", spec)),
+    };
+    let mut binding = KeyBinding {
+        key: parse_key(name).ok_or_else(|| format!("unknown key \"{}\"", spec))?,
+        ctrl: false,
+        alt: false,
+    };
+    for modifier in modifiers.split('+').filter(|modifier| !modifier.is_empty()) {
+        match modifier.to_lowercase().as_str() {
+            "ctrl" => binding.ctrl = true,
+            "alt" => binding.alt = true,
+            _ => return Err(format!("unknown modifier \"{}\" in \"{}\"", modifier, spec)),
+        }
+    }
+    Ok(binding)
+}
+
+fn parse_key(name: &str) -> Option<BoundKey> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(BoundKey::Text(c)),
+        _ => KEY_NAMES
+            .iter()
+            .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+            .map(|&(_, code)| BoundKey::Code(code)),
+    }
+}
+
+impl KeyBinding {
+    pub fn matches(&self, key: KeyPress) -> bool {
+        if self.ctrl != key.ctrl || self.alt != key.alt {
+            return false;
+        }
+        match self.key {
+            BoundKey::Code(code) => key.code == code,
+            // typed characters come as text, unless a modifier is held
+            BoundKey::Text(c) => {
+                key.printable == c
+                    && (key.code == KeyCode::Text || (key.code == KeyCode::Char && (key.ctrl || key.alt)))
+            }
+        }
+    }
+
+}
+
+impl Keymap {
+    pub fn command(&self, key: KeyPress) -> Option<KeyCommand> {
+        self.bindings
+            .iter()
+            .find(|(binding, _)| binding.matches(key))
+            .map(|&(_, command)| command)
+    }
+
+    pub fn keys_for(&self, command: KeyCommand) -> Vec<&KeyBinding> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| *bound == command)
+            .map(|(binding, _)| binding)
+            .collect()
+    }
+}
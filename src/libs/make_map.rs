@@ -1,141 +1,283 @@
-use std::cmp;
+use std::mem;
 use rand::Rng;
 use rand::distributions::{IndependentSample, Weighted, WeightedChoice};
 
 use tcod::colors::*;
-use tcod::console::*;
 use tcod::map::Map as FovMap;
-use tcod::input;
+use tcod::input::KeyCode;
 
 use crate::predefs::constants::*;
 use crate::predefs::structs::*;
 use crate::libs::render::*;
 use crate::libs::menu::{get_equipped_in_slot};
+use crate::libs::generators::{choose_generator, fallback_generator, walking_distances};
+use crate::libs::vaults::place_vault;
+use crate::libs::hazards::{place_hazards, swim};
+use crate::libs::traps::{spring_trap, trap_at};
+use crate::libs::secrets::place_secrets;
+use crate::libs::themes::{announce_level, choose_theme};
+use crate::libs::validate::{check_map, connect_regions, is_corridor};
 
 
-pub fn make_map(objects: &mut Vec<Object>, level: u32) -> Map{
-    
-    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
-    let mut rooms = vec![];
-    assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
-    objects.truncate(1);
-    
-
-    for _ in 0..MAX_ROOMS {
-        // random width and height
-        let w = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        let h = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        // random position without going out of the boundaries of the map
-        let x = rand::thread_rng().gen_range(0, MAP_WIDTH - w);
-        let y = rand::thread_rng().gen_range(0, MAP_HEIGHT - h);
-        let new_room = Rect::new(x, y, w, h);
-        let failed = rooms
-            .iter()
-            .any(|other_room| new_room.intersects_with(other_room));
-        if !failed {
-            // this means there are no intersections, so this room is valid
-
-            // "paint" it to the map's tiles
-            create_room(new_room, &mut map);
-            place_objects(new_room, &map, objects, level);
-
-            // center coordinates of the new room, will be useful later
-            let (new_x, new_y) = new_room.center();
-
-            if rooms.is_empty() {
-                // this is the first room, where the player starts at
-                objects[PLAYER].set_pos(new_x, new_y);
-            }else{
-                // all rooms after the first:
-                // connect it to the previous room with a tunnel
-
-                // center coordinates of the previous room
-                let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
-
-                // toss a coin (random bool value -- either true or false)
-                if rand::random() {
-                    // first move horizontally, then vertically
-                    create_h_tunnel(prev_x, new_x, prev_y, &mut map);
-                    create_v_tunnel(prev_y, new_y, new_x, &mut map);
-                } else {
-                    // first move vertically, then horizontally
-                    create_v_tunnel(prev_y, new_y, prev_x, &mut map);
-                    create_h_tunnel(prev_x, new_x, new_y, &mut map);
-                }
-            }
-            rooms.push(new_room);
-        }      
-        
-    }
-    // create stairs at the center of the last room
-    let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
-    let mut stairs = Object::new(last_room_x, last_room_y, '<', "stairs", WHITE, false);
+/// dig a new level and fill it up; also returns the vault on it, if there is one
+pub fn make_map<'a>(objects: &mut Objects, level: u32, theme: &ThemeDef, rng: &mut GameRng, content: &'a Content) -> (Map, Option<&'a VaultDef>) {
+    let mut tries = 0;
+    let (layout, vault) = loop {
+        // everything from the previous level (or a failed try) goes away, except the player
+        objects.retain(|id, _| id == PLAYER);
+
+        // settings that never work out (themes are made by hand) get plain rooms instead
+        tries += 1;
+        let fallback = tries > MAP_TRIES;
+        let generator = if fallback { fallback_generator() } else { choose_generator(theme, rng) };
+        let mut layout = match generator.generate(rng) {
+            Some(layout) => layout,
+            None => continue,
+        };
+        let vault = if fallback { None } else { place_vault(&mut layout.map, objects, level, theme, rng, content) };
+        // join up anything left out, and dig again if that's not enough
+        connect_regions(&mut layout.map);
+        if check_map(&layout.map, layout.start, layout.stairs).is_ok() {
+            place_hazards(&mut layout.map, objects, layout.start, layout.stairs, level, theme.hazards, rng);
+            place_secrets(&mut layout.map, objects, layout.start, layout.stairs, level, rng);
+            break (layout, vault);
+        }
+    };
+    objects[PLAYER].set_pos(layout.start.0, layout.start.1);
+    // the stairs go in first, so no trap ends up under them. there is no way up from
+    // the first level, and the player arrives on the way up on the others
+    let (stairs_x, stairs_y) = layout.stairs;
+    let mut stairs = Object::new(stairs_x, stairs_y, '>', "stairs down", WHITE, false);
     stairs.always_visible = true;
-    objects.push(stairs);
+    objects.insert(stairs);
+    if level > 1 {
+        let mut stairs = Object::new(layout.start.0, layout.start.1, '<', "stairs up", WHITE, false);
+        stairs.always_visible = true;
+        objects.insert(stairs);
+    }
 
-    map
+    for region in &layout.regions {
+        place_objects(region, &layout.map, objects, level, theme, rng, content);
+    }
+
+    (layout.map, vault)
 }
 /// Advance to the next level
-pub fn next_level(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
-    game.messages.add(
-        "You take a moment to rest, and recover your strength.",
-        VIOLET,
-    );
-    let heal_hp = objects[PLAYER].max_hp(game) / 2;
-    objects[PLAYER].heal(heal_hp, game);
+pub fn next_level(state: &mut GameState) {
+    let GameState { game, objects, .. } = state;
+    let below = game.dungeon_level + 1;
+    if game.levels.iter().any(|level| level.dungeon_level == below) {
+        game.messages.add(format!("You go back down to dungeon level {}.", below), RED);
+    } else {
+        game.messages.add(
+            "You take a moment to rest, and recover your strength.",
+            VIOLET,
+        );
+        let heal_hp = objects[PLAYER].max_hp(game) / 2;
+        objects[PLAYER].heal(heal_hp, game);
 
+        game.messages.add(
+            "After a rare moment of peace, you descend deeper into \
+            the heart of the dungeon...",
+            RED,
+        );
+    }
+    change_level(state, below, Some("stairs up"));
+}
+
+/// go back up the stairs, to a level the player has been on before
+pub fn previous_level(state: &mut GameState) {
+    let above = state.game.dungeon_level - 1;
+    state
+        .game
+        .messages
+        .add(format!("You climb back up to dungeon level {}.", above), LIGHT_VIOLET);
+    change_level(state, above, Some("stairs down"));
+}
+
+/// the player went into a chasm, and lands somewhere on the next level
+pub fn fall(state: &mut GameState) {
+    state.game.messages.add("You fall into the chasm!", RED);
+    let below = state.game.dungeon_level + 1;
+    change_level(state, below, None);
+    let GameState { game, objects, .. } = state;
     game.messages.add(
-        "After a rare moment of peace, you descend deeper into \
-        the heart of the dungeon...",
+        format!("You land hard, and lose {} hit points.", CHASM_DAMAGE),
         RED,
     );
-    game.dungeon_level += 1;
-    game.map = make_map(objects, game.dungeon_level);
-    initialise_fov(tcod, &game.map);
+    objects[PLAYER].take_damage(CHASM_DAMAGE, game);
 }
 
-fn create_room(room: Rect, map: &mut Map) {
-    // go through the tiles in the rectangle and make them passable
-    for x in (room.x1 + 1)..room.x2 {
-        for y in (room.y1 + 1)..room.y2 {
-            map[x as usize][y as usize] = Tile::empty();
+/// put the current level away and go to another one, as it was left if the player
+/// has been there before. the player arrives on the `arrival` staircase, or anywhere
+/// at all when there isn't one
+fn change_level(state: &mut GameState, dungeon_level: u32, arrival: Option<&str>) {
+    let GameState { game, objects, fov, content, .. } = state;
+    game.levels.push(Level {
+        dungeon_level: game.dungeon_level,
+        theme: game.theme.take(),
+        map: mem::take(&mut game.map),
+        objects: objects.remove_all_except(PLAYER),
+    });
+    game.dungeon_level = dungeon_level;
+    match game.levels.iter().position(|level| level.dungeon_level == dungeon_level) {
+        Some(index) => {
+            let level = game.levels.remove(index);
+            game.map = level.map;
+            game.theme = level.theme;
+            for object in level.objects {
+                objects.insert(object);
+            }
+        }
+        None => {
+            let theme = choose_theme(dungeon_level, &mut game.rng, content);
+            let (map, vault) = make_map(objects, dungeon_level, theme, &mut game.rng, content);
+            game.map = map;
+            game.theme = Some(theme.id.clone());
+            announce_level(dungeon_level, theme, vault, objects, game, content);
         }
     }
-}
 
-fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
-    // horizontal tunnel. `min()` and `max()` are used in case `x1 > x2`
-    for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
+    let stairs = arrival.and_then(|name| {
+        objects
+            .iter()
+            .find(|(_, object)| object.name == name)
+            .map(|(_, object)| object.pos())
+    });
+    let spot = match stairs {
+        // something may have wandered onto the stairs since the player left
+        Some(stairs) => nearest_free(stairs, &game.map, objects),
+        None => random_free_floor(&game.map, objects, &mut game.rng),
+    };
+    if let Some((x, y)) = spot {
+        objects[PLAYER].set_pos(x, y);
     }
+    initialise_fov(fov, &game.map);
 }
 
-fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
-    // vertical tunnel
-    for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
+/// the closest spot to `from` the player can stand on, walking there
+fn nearest_free(from: (i32, i32), map: &Map, objects: &Objects) -> Option<(i32, i32)> {
+    // the player isn't really on this level yet, wherever they were on the last one
+    let taken = |x: i32, y: i32| {
+        objects
+            .iter()
+            .any(|(id, object)| id != PLAYER && object.blocks && object.pos() == (x, y))
+    };
+    walking_distances(map, from)
+        .iter()
+        .enumerate()
+        .flat_map(|(x, column)| column.iter().enumerate().map(move |(y, steps)| (x as i32, y as i32, *steps)))
+        .filter_map(|(x, y, steps)| steps.map(|steps| (steps, x, y)))
+        .filter(|&(_, x, y)| !taken(x, y))
+        .min()
+        .map(|(_, x, y)| (x, y))
+}
+
+/// a random bit of plain floor with nothing standing on it
+pub fn random_free_floor(map: &Map, objects: &Objects, rng: &mut GameRng) -> Option<(i32, i32)> {
+    let free: Vec<(i32, i32)> = (0..MAP_WIDTH)
+        .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)))
+        .filter(|&(x, y)| map[x as usize][y as usize].kind == TileKind::Floor && !is_blocked(x, y, map, objects))
+        .collect();
+    if free.is_empty() {
+        None
+    } else {
+        Some(free[rng.gen_range(0, free.len())])
     }
 }
-pub fn move_by(id: usize, dx: i32, dy: i32, map: &Map, objects: &mut [Object]) {
+
+pub fn move_by(id: EntityId, dx: i32, dy: i32, fov: &FovMap, game: &mut Game, objects: &mut Objects) {
     let (x, y) = objects[id].pos();
-    
-    if !is_blocked(x + dx, y + dy, map, objects) {
-        objects[id].set_pos(x + dx, y + dy);
+    let (new_x, new_y) = (x + dx, y + dy);
+
+    // bumping into a closed door opens it, which takes the move; anything else in the way stops it
+    if open_door(new_x, new_y, &mut game.map) || is_blocked(new_x, new_y, &game.map, objects) {
+        return;
+    }
+    let here = objects[id].hazard_in(&game.map[x as usize][y as usize]);
+    let there = objects[id].hazard_in(&game.map[new_x as usize][new_y as usize]);
+    // monsters that can't swim keep out of deep water
+    if id != PLAYER && there == Some(Hazard::DeepWater) {
+        return;
+    }
+    // wading is slow going
+    if here == Some(Hazard::ShallowWater) && game.rng.gen::<f32>() < WADING_STUCK_CHANCE {
+        if id == PLAYER {
+            game.messages.add("You wade slowly through the water.", LIGHT_BLUE);
+        }
+        return;
+    }
+    objects[id].set_pos(new_x, new_y);
+    if id == PLAYER && there == Some(Hazard::DeepWater) {
+        swim(game);
+    }
+    if let Some(trap_id) = trap_at(new_x, new_y, objects) {
+        spring_trap(trap_id, id, fov, game, objects);
+    }
+}
+
+/// open the door at this spot, if there is a closed one that isn't locked
+pub fn open_door(x: i32, y: i32, map: &mut Map) -> bool {
+    let tile = &mut map[x as usize][y as usize];
+    if let TileKind::Door { open: false, locked: false } = tile.kind {
+        tile.kind = TileKind::Door { open: true, locked: false };
+        true
+    } else {
+        false
     }
 }
 
-pub fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
+/// shut every open door next to the player that has nothing in the way
+pub fn close_doors(game: &mut Game, objects: &Objects) -> PlayerAction {
+    let (player_x, player_y) = objects[PLAYER].pos();
+    let mut closed = 0;
+    let mut in_the_way = false;
+    for x in player_x - 1..=player_x + 1 {
+        for y in player_y - 1..=player_y + 1 {
+            if game.map[x as usize][y as usize].kind != (TileKind::Door { open: true, locked: false }) {
+                continue;
+            }
+            if objects.iter().any(|(_, object)| object.pos() == (x, y)) {
+                in_the_way = true;
+            } else {
+                game.map[x as usize][y as usize].kind = TileKind::Door { open: false, locked: false };
+                closed += 1;
+            }
+        }
+    }
+    match (closed, in_the_way) {
+        (0, true) => game.messages.add("Something is in the way of the door.", WHITE),
+        (0, false) => game.messages.add("There is no open door next to you.", WHITE),
+        (1, _) => game.messages.add("You close the door.", WHITE),
+        _ => game.messages.add("You close the doors.", WHITE),
+    }
+    if closed > 0 {
+        PlayerAction::TookTurn
+    } else {
+        PlayerAction::DidntTakeTurn
+    }
+}
+
+pub fn is_blocked(x: i32, y: i32, map: &Map, objects: &Objects) -> bool {
     // first test the map tile
-    if map[x as usize][y as usize].blocked {
+    if map[x as usize][y as usize].blocked() {
         return true;
     }
     // now check for any blocking objects
     objects
         .iter()
-        .any(|object| object.blocks && object.pos() == (x, y))
+        .any(|(_, object)| object.blocks && object.pos() == (x, y))
 }
 
-fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object> , level: u32) {
+fn place_objects(
+    region: &[(i32, i32)],
+    map: &Map,
+    objects: &mut Objects,
+    level: u32,
+    theme: &ThemeDef,
+    rng: &mut GameRng,
+    content: &Content,
+) {
     // maximum number of monsters per room
     let max_monsters = from_dungeon_level(
         &[
@@ -146,74 +288,15 @@ fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object> , level: u32)
         level,
     );
     // choose random number of monsters
-    let num_monsters = rand::thread_rng().gen_range(0, max_monsters + 1);
+    let num_monsters = rng.gen_range(0, max_monsters + 1);
 
     for _ in 0..num_monsters {
         // choose random spot for this monster
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
-        if !is_blocked(x, y, map, objects) {
-            // monster random table
-            // monster random table
-            let troll_chance = from_dungeon_level(
-                &[
-                    Transition {
-                        level: 3,
-                        value: 15,
-                    },
-                    Transition {
-                        level: 5,
-                        value: 30,
-                    },
-                    Transition {
-                        level: 7,
-                        value: 60,
-                    },
-                ],
-                level,
-            );
-            let monster_chances = &mut [
-                Weighted {
-                    weight: 80,
-                    item: "orc",
-                },
-                Weighted {
-                    weight: troll_chance,
-                    item: "troll",
-                },
-            ];
-            let monster_choice = WeightedChoice::new(monster_chances);
-            let mut monster = match monster_choice.ind_sample(&mut rand::thread_rng()) {
-                "orc" => {
-                    let mut orc = Object::new(x, y, 'o', "Orc", DESATURATED_GREEN, true);
-                    orc.fighter = Some(Fighter {
-                        base_max_hp: 20,
-                        hp: 20,
-                        base_defense: 0,
-                        base_power: 4,
-                        xp: 35,
-                        on_death: DeathCallback::Monster,
-                    });
-                    orc.ai = Some(Ai::Basic);
-                    orc
-                }
-                "troll" => {
-                    let mut troll = Object::new(x, y, 'T', "Troll", DARKER_GREEN, true);
-                    troll.fighter = Some(Fighter {
-                        base_max_hp: 30,
-                        hp: 30,
-                        base_defense: 2,
-                        base_power: 8,
-                        xp: 100,
-                        on_death: DeathCallback::Monster,
-                    });
-                    troll.ai = Some(Ai::Basic);
-                    troll
-                }
-                _ => unreachable!(),
-            };
-            monster.alive = true;
-            objects.push(monster);
+        let (x, y) = region[rng.gen_range(0, region.len())];
+        // not in narrow passages, where nothing could get past it
+        if !is_blocked(x, y, map, objects) && !is_corridor(x, y, map) && map[x as usize][y as usize].kind == TileKind::Floor {
+            let monster = random_monster(level, theme, rng, content).create(x, y);
+            objects.insert(monster);
         }
     }
     let max_items = from_dungeon_level(
@@ -224,147 +307,109 @@ fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object> , level: u32)
         level,
     );
 
-    let num_items = rand::thread_rng().gen_range(0, max_items + 1);
+    let num_items = rng.gen_range(0, max_items + 1);
     for _ in 0..num_items {
         // choose random spot for this item
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
+        let (x, y) = region[rng.gen_range(0, region.len())];
         
-        // only place it if the tile is not blocked
-        if !is_blocked(x, y, map, objects) {
-            // item random table
-            let item_chances = &mut [
-            // healing potion always shows up, even if all other items have 0 chance
-            Weighted {
-                weight: 35,
-                item: Item::Heal,
-            },
-            Weighted {
-                weight: from_dungeon_level(
-                    &[Transition {
-                        level: 4,
-                        value: 25,
-                    }],
-                    level,
-                ),
-                item: Item::ScrollLightning,
-            },
-            Weighted {
-                weight: from_dungeon_level(
-                    &[Transition {
-                        level: 6,
-                        value: 25,
-                    }],
-                    level,
-                ),
-                item: Item::ScrollFireball,
-            },
-            Weighted {
-                weight: from_dungeon_level(
-                    &[Transition {
-                        level: 2,
-                        value: 10,
-                    }],
-                    level,
-                ),
-                item: Item::ScrollConfusion,
-            },
-            Weighted {
-                weight: from_dungeon_level(&[Transition { level: 4, value: 5 }], level),
-                item: Item::Sword, 
-            },
-            Weighted {
-                weight: from_dungeon_level(
-                    &[Transition {
-                        level: 8,
-                        value: 15,
-                    }],
-                    level,
-                ),
-                item: Item::Shield, 
-            },
-        ];
-        let item_choice = WeightedChoice::new(item_chances);
-            let mut item = match item_choice.ind_sample(&mut rand::thread_rng()) {
-                Item::Heal => {
-                    // create a healing potion
-                    let mut object = Object::new(x, y, '!', "healing potion", VIOLET, false);
-                    object.item = Some(Item::Heal);
-                    object
-                }
-                Item::ScrollLightning => {
-                    // create a lightning bolt scroll
-                    let mut object =
-                        Object::new(x, y, '#', "scroll of lightning bolt", LIGHT_YELLOW, false);
-                    object.item = Some(Item::ScrollLightning);
-                    object
-                }
-                Item::ScrollFireball => {
-                    // create a fireball scroll
-                    let mut object =
-                        Object::new(x, y, '#', "scroll of fireball", LIGHT_YELLOW, false);
-                    object.item = Some(Item::ScrollFireball);
-                    object
-                }
-                Item::ScrollConfusion => {
-                    // create a confuse scroll
-                    let mut object =
-                        Object::new(x, y, '#', "scroll of confusion", LIGHT_YELLOW, false);
-                    object.item = Some(Item::ScrollConfusion);
-                    object
-                }
-                Item::Sword => {
-                    // create a sword
-                    let mut object = Object::new(x, y, '/', "sword", SKY, false);
-                    object.item = Some(Item::Sword);
-                    object.equipment = Some(Equipment {
-                        equipped: false,
-                        slot: Slot::RightHand,
-                        max_hp_bonus: 0,
-                        defense_bonus: 0,
-                        power_bonus: 3,
-                    });
-                    object
-                }
-                Item::Shield => {
-                    // create a shield
-                    let mut object = Object::new(x, y, '[', "shield", DARKER_ORANGE, false);
-                    object.item = Some(Item::Shield);
-                    object.equipment = Some(Equipment {
-                        equipped: false,
-                        slot: Slot::LeftHand,
-                        max_hp_bonus: 0,
-                        defense_bonus: 1,
-                        power_bonus: 0,
-                    });
-                    object
-                }
-            };
+        // only place it if the tile is not blocked, nor under water or the like
+        if !is_blocked(x, y, map, objects) && map[x as usize][y as usize].kind == TileKind::Floor {
+            let mut item = random_item(level, theme, rng, content).create(x, y);
             item.always_visible = true;
-            objects.push(item);
+            objects.insert(item);
 
                     
         };
     };
+
+    // chance of a trap, in percent
+    let trap_chance = from_dungeon_level(
+        &[
+            Transition { level: 1, value: 25 },
+            Transition { level: 4, value: 35 },
+            Transition { level: 7, value: 50 },
+        ],
+        level,
+    );
+    if rng.gen_range(0, 100) < trap_chance {
+        let (x, y) = region[rng.gen_range(0, region.len())];
+        // on bare floor, with nothing else there
+        let taken = objects.iter().any(|(_, object)| object.pos() == (x, y));
+        if map[x as usize][y as usize].kind == TileKind::Floor && !taken {
+            if let Some(trap) = random_trap(level, rng, content) {
+                objects.insert(trap.create(x, y));
+            }
+        }
+    }
+}
+
+/// a monster from the random table of this dungeon level, as the theme has it
+pub fn random_monster<'a>(level: u32, theme: &ThemeDef, rng: &mut GameRng, content: &'a Content) -> &'a MonsterDef {
+    let monster_chances = &mut content
+        .monsters
+        .iter()
+        .enumerate()
+        .map(|(index, monster)| Weighted {
+            weight: from_dungeon_level(theme.monster_spawn(monster), level),
+            item: index,
+        })
+        .collect::<Vec<_>>();
+    let monster_choice = WeightedChoice::new(monster_chances);
+    &content.monsters[monster_choice.ind_sample(rng)]
+}
+
+/// an item from the random table of this dungeon level, as the theme has it
+pub fn random_item<'a>(level: u32, theme: &ThemeDef, rng: &mut GameRng, content: &'a Content) -> &'a ItemDef {
+    let item_chances = &mut content
+        .items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| Weighted {
+            weight: from_dungeon_level(theme.item_spawn(item), level),
+            item: index,
+        })
+        .collect::<Vec<_>>();
+    let item_choice = WeightedChoice::new(item_chances);
+    &content.items[item_choice.ind_sample(rng)]
+}
+
+/// a trap from the random table of this dungeon level, if any can be found this deep
+pub fn random_trap<'a>(level: u32, rng: &mut GameRng, content: &'a Content) -> Option<&'a TrapDef> {
+    let trap_chances = &mut content
+        .traps
+        .iter()
+        .enumerate()
+        .map(|(index, trap)| Weighted {
+            weight: from_dungeon_level(&trap.spawn, level),
+            item: index,
+        })
+        .collect::<Vec<_>>();
+    if trap_chances.iter().all(|chance| chance.weight == 0) {
+        return None;
+    }
+    let trap_choice = WeightedChoice::new(trap_chances);
+    Some(&content.traps[trap_choice.ind_sample(rng)])
 }
-   
 
 /// return a string with the names of all objects under the mouse
-pub fn get_names_under_mouse(mouse: input::Mouse, objects: &[Object], fov_map: &FovMap) -> String {
-    let (x, y) = (mouse.cx as i32, mouse.cy as i32);
+pub fn get_names_under_mouse(mouse: MouseState, camera: (i32, i32), objects: &Objects, fov_map: &FovMap) -> String {
+    let (x, y) = match screen_to_map(mouse.x, mouse.y, camera) {
+        Some(pos) => pos,
+        None => return String::new(),
+    };
 
     // create a list with the names of all objects at the mouse's coordinates and in FOV
     let names = objects
         .iter()
-        .filter(|obj| obj.pos() == (x, y) && fov_map.is_in_fov(obj.x, obj.y))
-        .map(|obj| obj.name.clone())
+        .filter(|(_, obj)| obj.pos() == (x, y) && fov_map.is_in_fov(obj.x, obj.y) && !obj.trap.is_some_and(|trap| trap.hidden))
+        .map(|(_, obj)| obj.name.clone())
         .collect::<Vec<_>>();
 
     names.join(", ") // join the names, separated by commas
 }
 
 /// add to the player's inventory and remove from the map
-pub fn pick_item_up(object_id: usize, game: &mut Game, objects: &mut Vec<Object>) {
+pub fn pick_item_up(object_id: EntityId, game: &mut Game, objects: &mut Objects) {
     if game.inventory.len() >= 26 {
         game.messages.add(
             format!(
@@ -374,7 +419,7 @@ pub fn pick_item_up(object_id: usize, game: &mut Game, objects: &mut Vec<Object>
             RED,
         );
     } else {
-        let item = objects.swap_remove(object_id);
+        let item = objects.remove(object_id).unwrap();
         game.messages
             .add(format!("You picked up a {}!", item.name), GREEN);
         let index = game.inventory.len();
@@ -391,15 +436,15 @@ pub fn pick_item_up(object_id: usize, game: &mut Game, objects: &mut Vec<Object>
 }
 
 /// find closest enemy, up to a maximum range, and in the player's FOV
-pub fn closest_monster(tcod: &Tcod, objects: &[Object], max_range: i32) -> Option<usize> {
+pub fn closest_monster(fov: &FovMap, objects: &Objects, max_range: i32) -> Option<EntityId> {
     let mut closest_enemy = None;
     let mut closest_dist = (max_range + 1) as f32; // start with (slightly more than) maximum range
 
-    for (id, object) in objects.iter().enumerate() {
+    for (id, object) in objects.iter() {
         if (id != PLAYER)
             && object.fighter.is_some()
             && object.ai.is_some()
-            && tcod.fov.is_in_fov(object.x, object.y)
+            && fov.is_in_fov(object.x, object.y)
         {
             // calculate distance between this object and the player
             let dist = objects[PLAYER].distance_to(object);
@@ -413,50 +458,41 @@ pub fn closest_monster(tcod: &Tcod, objects: &[Object], max_range: i32) -> Optio
     closest_enemy
 }
 pub fn target_tile(
-    tcod: &mut Tcod,
-    game: &mut Game,
-    objects: &[Object],
+    ui: &mut Ui,
+    state: &GameState,
     max_range: Option<f32>,
 ) -> Option<(i32, i32)> {
-    use tcod::input::*;
     loop {
         // render the screen. this erases the inventory and shows the names of
         // objects under the mouse.
-        tcod.root.flush();
-        let event = input::check_for_event(input::KEY_PRESS | input::MOUSE).map(|e| e.1);
-        match event {
-            Some(Event::Mouse(m)) => tcod.mouse = m,
-            Some(Event::Key(k)) => tcod.key = k,
-            None => tcod.key = Default::default(),
-        }
-        render_all(tcod, game, objects, false);
-
-        let (x, y) = (tcod.mouse.cx as i32, tcod.mouse.cy as i32);
+        ui.flush();
+        ui.poll();
+        render_all(ui, state);
 
-        let in_fov = (x < MAP_WIDTH) && (y < MAP_HEIGHT) && tcod.fov.is_in_fov(x, y);
-        let in_range = max_range.map_or(true, |range| objects[PLAYER].distance(x, y) <= range);
-        if tcod.mouse.lbutton_pressed && in_fov && in_range {
-            return Some((x, y));
+        // the mouse is on screen; what it points at is somewhere on the map
+        if let Some((x, y)) = screen_to_map(ui.mouse.x, ui.mouse.y, camera(&state.objects)) {
+            let in_fov = state.fov.is_in_fov(x, y);
+            let in_range = max_range.is_none_or(|range| state.objects[PLAYER].distance(x, y) <= range);
+            if ui.mouse.left_click && in_fov && in_range {
+                return Some((x, y));
+            }
         }
-        if tcod.mouse.rbutton_pressed || tcod.key.code == input::KeyCode::Escape {
-            return None; // cancel if the player right-clicked or pressed Escape
+        if ui.mouse.right_click || ui.key.code == KeyCode::Escape || ui.closed() {
+            return None; // cancel if the player right-clicked, pressed Escape or went away
         }
     }
 }
 pub fn target_monster(
-    tcod: &mut Tcod,
-    game: &mut Game,
-    objects: &[Object],
+    ui: &mut Ui,
+    state: &GameState,
     max_range: Option<f32>,
-) -> Option<usize> {
+) -> Option<EntityId> {
     loop {
-        match target_tile(tcod, game, objects, max_range) {
+        match target_tile(ui, state, max_range) {
             Some((x, y)) => {
                 // return the first clicked monster, otherwise continue looping
-                for (id, obj) in objects.iter().enumerate() {
-                    if obj.pos() == (x, y) && obj.fighter.is_some() && id != PLAYER {
-                        return Some(id);
-                    }
+                if let Some(id) = monster_at(x, y, &state.objects) {
+                    return Some(id);
                 }
             }
             None => return None,
@@ -464,25 +500,69 @@ pub fn target_monster(
     }
 }
 
-pub fn initialise_fov(tcod: &mut Tcod, map: &Map) {
+/// the first monster (anything that can fight, except the player) at a position
+pub fn monster_at(x: i32, y: i32, objects: &Objects) -> Option<EntityId> {
+    objects
+        .iter()
+        .find(|&(id, obj)| obj.pos() == (x, y) && obj.fighter.is_some() && id != PLAYER)
+        .map(|(id, _)| id)
+}
+
+pub fn initialise_fov(fov: &mut FovMap, map: &Map) {
     // create the FOV map, according to the generated map
     for y in 0..MAP_HEIGHT {
         for x in 0..MAP_WIDTH {
-            tcod.fov.set(
+            fov.set(
                 x,
                 y,
-                !map[x as usize][y as usize].block_sight,
-                !map[x as usize][y as usize].blocked,
+                !map[x as usize][y as usize].block_sight(),
+                !map[x as usize][y as usize].blocked(),
             );
         }
     }
-    tcod.con.clear();
 }
 
-fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
+pub fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
     table
         .iter()
         .rev()
         .find(|transition| level >= transition.level)
         .map_or(0, |transition| transition.value)
-}
\ No newline at end of file
+}
+
+/// pick one of the choices, as likely as its weight. the rng is only rolled when there
+/// is a choice, so content with a single option leaves the rest of the run as it was
+pub fn pick_weighted<T: Clone>(choices: &mut [Weighted<T>], rng: &mut GameRng) -> T {
+    if choices.iter().filter(|choice| choice.weight > 0).count() > 1 {
+        WeightedChoice::new(choices).ind_sample(rng)
+    } else {
+        choices.iter().find(|choice| choice.weight > 0).expect("content is validated").item.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::content::load_content;
+
+    #[test]
+    fn hopeless_themes_still_get_a_level() {
+        let content = load_content().expect("the game data loads");
+        let mut theme = content.theme("flooded_caverns").unwrap().clone();
+        // hardly any floor, and needing nearly the whole map to be floor
+        let kind = GeneratorKind::Caves { wall_chance: 0.99, smoothing_passes: 5, min_floor: 8000 };
+        theme.generators = vec![GeneratorDef { weight: 100, kind }];
+
+        let mut objects = Objects::new();
+        objects.insert(Object::new(0, 0, '@', "player", WHITE, true));
+        let mut rng = GameRng::new(1);
+        let (map, vault) = make_map(&mut objects, 3, &theme, &mut rng, &content);
+        assert!(vault.is_none());
+        let stairs = objects
+            .iter()
+            .find(|(_, object)| object.name == "stairs down")
+            .map(|(_, object)| object.pos())
+            .unwrap();
+        assert_eq!(check_map(&map, objects[PLAYER].pos(), stairs), Ok(()));
+    }
+}
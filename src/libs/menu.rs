@@ -1,70 +1,49 @@
 
-use std::error::Error;
-use std::fs::File;
-use std::io::{Read, Write};
-
-
-use tcod::console::*;
+use tcod::console::TextAlignment;
 use tcod::colors::*;
-use tcod::input;
 use crate::predefs::constants::*;
 use crate::predefs::structs::*;
 use crate::libs::itens_effects::*;
 use crate::libs::make_map::*;
 use crate::libs::handle_keys::*;
 use crate::libs::render::*;
-use crate::libs::ai::*;
+use crate::libs::replay::save_replay;
+use crate::libs::save::*;
+use crate::libs::themes::{announce_level, choose_theme};
+use crate::libs::canvas::height_rect;
 
-pub fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut Root) -> Option<usize> {
+pub fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, ui: &mut Ui) -> Option<usize> {
     assert!(
         options.len() <= 26,
         "Cannot have a menu with more than 26 options."
     );
     // calculate total height for the header (after auto-wrap) and one line per option
-    let header_height = root.get_height_rect(0, 0, width, SCREEN_HEIGHT, header);
-    let height = options.len() as i32 + header_height;
-    // create an off-screen console that represents the menu's window
-    let mut window = Offscreen::new(width, height);
-    // print the header, with auto-wrap
-    window.set_default_foreground(WHITE);
-    window.print_rect_ex(
-        0,
-        0,
-        width,
-        height,
-        BackgroundFlag::None,
-        TextAlignment::Left,
-        header,
-    );
-    // calculate total height for the header (after auto-wrap) and one line per option
     let header_height = if header.is_empty() {
         0
     } else {
-        root.get_height_rect(0, 0, width, SCREEN_HEIGHT, header)
+        height_rect(width, header)
     };
     let height = options.len() as i32 + header_height;
+    // create an off-screen canvas that represents the menu's window
+    let mut window = Canvas::new(width, height);
+    // print the header, with auto-wrap
+    window.print_rect(0, 0, width, header, WHITE);
     // print all the options
     for (index, option_text) in options.iter().enumerate() {
         let menu_letter = (b'a' + index as u8) as char;
         let text = format!("({}) {}", menu_letter, option_text.as_ref());
-        window.print_ex(
-            0,
-            header_height + index as i32,
-            BackgroundFlag::None,
-            TextAlignment::Left,
-            text,
-        );
+        window.print(0, header_height + index as i32, &text, WHITE, TextAlignment::Left);
     }
     // blit the contents of "window" to the root console
     let x = SCREEN_WIDTH / 2 - width / 2;
     let y = SCREEN_HEIGHT / 2 - height / 2;
-    blit(&window, (0, 0), (width, height), root, (x, y), 1.0, 0.6);
+    window.blit(&mut ui.root, x, y, 0.6);
     // present the root console to the player and wait for a key-press
-    root.flush();
-    let key = root.wait_for_keypress(true);
+    ui.flush();
+    let key = ui.wait_for_key();
 
     // convert the ASCII code to an index; if it corresponds to an option, return it
-    if key.printable.is_alphabetic() {
+    if key.printable.is_ascii_alphabetic() {
         let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
         if index < options.len() {
             Some(index)
@@ -76,9 +55,9 @@ pub fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut R
     }
 }
 
-pub fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option<usize> {
+pub fn inventory_menu(inventory: &[Object], header: &str, ui: &mut Ui) -> Option<usize> {
     // how a menu with each item of the inventory as an option
-    let options = if inventory.len() == 0 {
+    let options = if inventory.is_empty() {
         vec!["Inventory is empty.".into()]
     } else {
         inventory
@@ -95,40 +74,39 @@ pub fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Op
     .collect()
     };
 
-    let inventory_index = menu(header, &options, INVENTORY_WIDTH, root);
+    let inventory_index = menu(header, &options, INVENTORY_WIDTH, ui);
 
     // if an item was chosen, return it
-    if inventory.len() > 0 {
+    if !inventory.is_empty() {
         inventory_index
     } else {
         None
     }
 }
 
-pub fn use_item(inventory_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
+pub fn use_item(inventory_id: usize, target: Option<(i32, i32)>, state: &mut GameState) {
     use Item::*;
     // just call the "use_function" if it is defined
-    if let Some(item) = game.inventory[inventory_id].item {
-        let on_use = match item {
-            Heal => cast_heal,
-            ScrollLightning => cast_lightning,
-            ScrollConfusion => cast_confusion,
-            ScrollFireball => cast_fireball,
-            Sword => toggle_equipment,
-            Shield => toggle_equipment,
-
+    if let Some(item) = state.game.inventory[inventory_id].item {
+        let result = match item {
+            Heal { amount } => cast_heal(amount, state),
+            Lightning { damage, range } => cast_lightning(damage, range, state),
+            Confusion { turns, .. } => cast_confusion(turns, target, state),
+            Fireball { damage, radius } => cast_fireball(damage, radius, target, state),
+            Equipment => toggle_equipment(inventory_id, state),
         };
-        match on_use(inventory_id, tcod, game, objects) {
+        match result {
             UseResult::UsedUp => {
                 // destroy after use, unless it was cancelled for some reason
-                game.inventory.remove(inventory_id);
+                state.game.inventory.remove(inventory_id);
             }
             UseResult::UsedAndKept => {}
             UseResult::Cancelled => {
-                game.messages.add("Cancelled", WHITE);
+                state.game.messages.add("Cancelled", WHITE);
             }
         }
     } else {
+        let game = &mut state.game;
         game.messages.add(
             format!("The {} cannot be used.", game.inventory[inventory_id].name),
             WHITE,
@@ -140,7 +118,7 @@ pub fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
         if item
             .equipment
             .as_ref()
-            .map_or(false, |e| e.equipped && e.slot == slot)
+            .is_some_and(|e| e.equipped && e.slot == slot)
         {
             return Some(inventory_id);
         }
@@ -148,17 +126,23 @@ pub fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
     None
 }
 
-pub fn drop_item(inventory_id: usize, game: &mut Game, objects: &mut Vec<Object>) {
+pub fn drop_item(inventory_id: usize, game: &mut Game, objects: &mut Objects) {
     let mut item = game.inventory.remove(inventory_id);
     if item.equipment.is_some() {
         item.dequip(&mut game.messages);
     }
-    item.set_pos(objects[PLAYER].x, objects[PLAYER].y);
+    let (x, y) = objects[PLAYER].pos();
+    if game.map[x as usize][y as usize].kind == TileKind::Hazard(Hazard::DeepWater) {
+        game.messages
+            .add(format!("You let go of the {}, and it sinks out of sight.", item.name), YELLOW);
+        return;
+    }
+    item.set_pos(x, y);
     game.messages
         .add(format!("You dropped a {}.", item.name), YELLOW);
-    objects.push(item);
+    objects.insert(item);
 }
-pub fn new_game(tcod: &mut Tcod) -> (Game, Vec<Object>) {
+pub fn new_game(seed: u64, content: &Content) -> GameState {
     // create object representing the player
     let mut player = Object::new(0, 0, '@', "player", WHITE, true);
     player.alive = true;
@@ -171,125 +155,202 @@ pub fn new_game(tcod: &mut Tcod) -> (Game, Vec<Object>) {
         on_death: DeathCallback::Player,  // <1>
     });
     // the list of objects with just the player
-    let mut objects = vec![player];
+    let mut objects = Objects::new();
+    objects.insert(player);
+    let mut rng = GameRng::new(seed);
+    let theme = choose_theme(1, &mut rng, content);
+    // generate map (at this point it's not drawn to the screen)
+    let (map, vault) = make_map(&mut objects, 1, theme, &mut rng, content);
 
     let mut game = Game {
-        // generate map (at this point it's not drawn to the screen)
-        map: make_map(&mut objects,1),
+        map,
         messages: Messages::new(),
         inventory: vec![],
         dungeon_level: 1,  
+        levels: vec![],
+        theme: Some(theme.id.clone()),
+        turns: 0,
+        confused_turns: 0,
+        seed: Some(seed),
+        rng,
+        commands: vec![],
     };
-    // initial equipment: a dagger
-    let mut dagger = Object::new(0, 0, '-', "dagger", SKY, false);
-    dagger.item = Some(Item::Sword);
-    dagger.equipment = Some(Equipment {
-        equipped: true,
-        slot: Slot::LeftHand,
-        max_hp_bonus: 0,
-        defense_bonus: 0,
-        power_bonus: 2,
-    });
-    game.inventory.push(dagger);
-
-    initialise_fov(tcod, &game.map);
+    // initial equipment, ready to use
+    for id in &content.starting_inventory {
+        let mut item = content.item(id).expect("starting items are validated").create(0, 0);
+        if let Some(slot) = item.equipment.map(|e| e.slot) {
+            if get_equipped_in_slot(slot, &game.inventory).is_none() {
+                item.equipment.as_mut().unwrap().equipped = true;
+            }
+        }
+        game.inventory.push(item);
+    }
 
     // a warm welcoming message!
     game.messages.add(
         "Welcome stranger! Prepare to perish in the Tombs of the Ancient Kings.",
         RED,
     );
+    announce_level(1, theme, vault, &objects, &mut game, content);
 
-    (game, objects)
+    GameState::new(game, objects, content.clone())
 }
-pub fn play_game(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
-    // force FOV "recompute" first time through the game loop
-    use tcod::input::*;
-    let mut previous_player_position = (-1, -1);
-
-    while !tcod.root.window_closed() {
+/// play in the given save slot until the player quits
+pub fn play_game(ui: &mut Ui, state: &mut GameState, slot: usize, save_format: SaveFormat) {
+    state.save_slot = Some(slot);
+    while !ui.closed() {
         // clear the screen of the previous frame
-        tcod.con.clear();
+        ui.con.clear();
 
-        match input::check_for_event(input::MOUSE | input::KEY_PRESS).map(|e| e.1) {
-            Some(Event::Mouse(m)) => tcod.mouse = m,
-            Some(Event::Key(k)) => tcod.key = k,
-            _ => tcod.key = Default::default(),
-        }
+        ui.poll();
 
         // render the screen
-        let fov_recompute = previous_player_position != (objects[PLAYER].pos());  // <1>
-        render_all(tcod, game, &objects, fov_recompute);
+        render_all(ui, state);
 
-        tcod.root.flush();
-        level_up(tcod, game, objects);
+        ui.flush();
 
         // handle keys and exit game if needed
-        previous_player_position = objects[PLAYER].pos();
-        let player_action = handle_keys(tcod, game, objects);
-        if player_action == PlayerAction::Exit {
-            save_game(game, objects).unwrap();
-            break;
+        let command = match handle_keys(ui, state) {
+            KeyAction::Command(command) => command,
+            KeyAction::Ignored => continue,
+            KeyAction::Exit => break,
+        };
+
+        // let the game run, answering whatever it asks along the way
+        let mut events = state.step(command);
+        keep_saved(ui, state, slot, save_format, &events);
+        while let Some(prompt) = pending_prompt(&events) {
+            // nobody is left to answer when the window is closed
+            let answer = match answer_prompt(ui, state, prompt) {
+                Some(answer) => answer,
+                None => break,
+            };
+            events = state.step(answer);
+            keep_saved(ui, state, slot, save_format, &events);
         }
+    }
+    // the player left, or closed the window
+    autosave(ui, state, slot, save_format);
+    // keep the run around, so it can be watched again or attached to a bug report. without
+    // its seed it would replay a different dungeon
+    if state.game.seed.is_some() {
+        if let Err(e) = save_replay(&state.game) {
+            msgbox(&format!("\nCould not save the replay of this run: {}\n", e), 50, ui);
+        }
+    }
+}
 
-        // let monsters take their turn
-        if objects[PLAYER].alive && player_action != PlayerAction::DidntTakeTurn {
-            for id in 0..objects.len() {
-                if objects[id].ai.is_some() {
-                    ai_take_turn(id, tcod, game, objects);
-                }
+/// save on every new level and now and then; the game itself deletes the save on death
+fn keep_saved(ui: &mut Ui, state: &GameState, slot: usize, save_format: SaveFormat, events: &[GameEvent]) {
+    for event in events {
+        match event {
+            GameEvent::LevelChanged(_) => autosave(ui, state, slot, save_format),
+            GameEvent::TurnTaken if state.game.turns.is_multiple_of(AUTOSAVE_TURNS) => {
+                autosave(ui, state, slot, save_format)
             }
+            _ => {}
         }
     }
 }
 
-pub fn main_menu(tcod: &mut Tcod) {
-    let img = tcod::image::Image::from_file("menu_background.png") 
-        .ok()
-        .expect("Background image not found");
-        
-    
+fn autosave(ui: &mut Ui, state: &GameState, slot: usize, save_format: SaveFormat) {
+    // the dead don't get saved, their slot is already free
+    if !state.objects[PLAYER].alive {
+        return;
+    }
+    if let Err(e) = save_game(slot, save_format, &state.game, &state.objects) {
+        msgbox(&format!("\nCould not save the game: {}\n", e), 50, ui);
+    }
+}
+
+fn pending_prompt(events: &[GameEvent]) -> Option<Prompt> {
+    events.iter().find_map(|event| match event {
+        GameEvent::Prompt(prompt) => Some(*prompt),
+        _ => None,
+    })
+}
+
+/// ask the player whatever the game needs to know to continue
+fn answer_prompt(ui: &mut Ui, state: &GameState, prompt: Prompt) -> Option<PlayerCommand> {
+    match prompt {
+        Prompt::LevelUp => {
+            // show the outcome of the last turn behind the menu
+            ui.con.clear();
+            render_all(ui, state);
+            level_up_menu(ui, &state.objects[PLAYER]).map(PlayerCommand::LevelUp)
+        }
+        Prompt::Target { max_range, monster: true, .. } => {
+            let target = target_monster(ui, state, max_range);
+            Some(PlayerCommand::Target(target.map(|id| state.objects[id].pos())))
+        }
+        Prompt::Target { max_range, monster: false, .. } => {
+            Some(PlayerCommand::Target(target_tile(ui, state, max_range)))
+        }
+    }
+}
+
+/// the stat to raise, or nothing if the window was closed before choosing one
+fn level_up_menu(ui: &mut Ui, player: &Object) -> Option<Stat> {
+    let fighter = player.fighter.unwrap();
+    while !ui.closed() {
+        // keep asking until a choice is made
+        let choice = menu(
+            "Level up! Choose a stat to raise:\n",
+            &[
+                format!("Constitution (+20 HP, from {})", fighter.base_max_hp),
+                format!("Strength (+1 attack, from {})", fighter.base_power),
+                format!("Agility (+1 defense, from {})", fighter.base_defense),
+            ],
+            LEVEL_SCREEN_WIDTH,
+            ui,
+        );
+        match choice {
+            Some(0) => return Some(Stat::Constitution),
+            Some(1) => return Some(Stat::Strength),
+            Some(2) => return Some(Stat::Agility),
+            _ => {}
+        }
+    }
+    None
+}
 
-    while !tcod.root.window_closed() {  
-        // show the background image, at twice the regular console resolution
-        tcod::image::blit_2x(&img, (0, 0), (-1, -1), &mut tcod.root, (0, 0));
-        tcod.root.set_default_foreground(LIGHT_YELLOW);
-        tcod.root.print_ex(
+/// `seed` fixes the dungeon of new games, otherwise every game gets a random one
+pub fn main_menu(ui: &mut Ui, content: &Content, seed: Option<u64>, save_format: SaveFormat) {
+    while !ui.closed() {  
+        // show the background image, if the backend can
+        ui.root.clear();
+        ui.backend.draw_image("menu_background.png", &mut ui.root);
+        ui.root.print(
             SCREEN_WIDTH / 2,
             SCREEN_HEIGHT / 2 - 4,
-            BackgroundFlag::None,
-            TextAlignment::Center,
             "A Robber",
+            LIGHT_YELLOW,
+            TextAlignment::Center,
         );
-        tcod.root.print_ex(
+        ui.root.print(
             SCREEN_WIDTH / 2,
             SCREEN_HEIGHT - 2,
-            BackgroundFlag::None,
-            TextAlignment::Center,
             "/Eduardo Bilk",
+            LIGHT_YELLOW,
+            TextAlignment::Center,
         );
 
         // show options and wait for the player's choice
-        let choices = &["Play a new game", "Continue last game", "Quit"];
-        let choice = menu("", choices, 24, &mut tcod.root);
+        let choices = &["Play a new game", "Continue", "Quit"];
+        let choice = menu("", choices, 24, ui);
 
         match choice {
             Some(0) => { 
-                let (mut game, mut objects) = new_game(tcod);
-                play_game(tcod, &mut game, &mut objects);
-            }
-            Some(1) => {
-                match load_game() {
-                    Ok((mut game, mut objects)) => {
-                        initialise_fov(tcod, &game.map);
-                        play_game(tcod, &mut game, &mut objects);
-                    }
-                    Err(_e) => {
-                        msgbox(&format!("\nNo saved game to load.\n"), 24, &mut tcod.root);
-                        continue;
-                    }
+                // take the first empty slot, or ask which game to give up
+                let slot = free_slot().or_else(|| {
+                    choose_slot("All save slots are in use. Overwrite which one?\n", ui)
+                });
+                if let Some(slot) = slot {
+                    let mut state = new_game(seed.unwrap_or_else(rand::random), content);
+                    play_game(ui, &mut state, slot, save_format);
                 }
             }
+            Some(1) => continue_menu(ui, content, save_format),
             Some(2) => { break } // quit ...}
             _ => {}
         }
@@ -297,22 +358,65 @@ pub fn main_menu(tcod: &mut Tcod) {
     }
 }
 
-fn save_game(game: &Game, objects: &[Object]) -> Result<(), Box<dyn Error>> {  
-    let save_data = serde_json::to_string(&(game, objects))?;  
-    let mut file = File::create("savegame")?;  
-    file.write_all(save_data.as_bytes())?;  
-    Ok(())  
+/// pick a saved game to continue, or to delete
+fn continue_menu(ui: &mut Ui, content: &Content, save_format: SaveFormat) {
+    let slot = match choose_slot("Choose a saved game:\n", ui) {
+        Some(slot) => slot,
+        None => return,
+    };
+    if let Err(e) = save_summary(slot) {
+        let text = match e {
+            SaveError::NotFound => "\nThis slot is empty.\n".into(),
+            e => format!("\n{}\n", e),
+        };
+        msgbox(&text, 50, ui);
+        return;
+    }
+
+    let header = format!("Slot {}:\n", slot + 1);
+    match menu(&header, &["Continue", "Delete", "Back"], 24, ui) {
+        Some(0) => match load_game(slot) {
+            Ok((game, objects)) => {
+                let mut state = GameState::new(game, objects, content.clone());
+                play_game(ui, &mut state, slot, save_format);
+            }
+            Err(e) => msgbox(&format!("\n{}\n", e), 50, ui),
+        },
+        Some(1) => {
+            let question = format!("Delete the game in slot {} for good?\n", slot + 1);
+            if menu(&question, &["No", "Yes"], 24, ui) == Some(1) {
+                if let Err(e) = delete_save(slot) {
+                    msgbox(&format!("\n{}\n", e), 50, ui);
+                }
+            }
+        }
+        _ => {}
+    }
 }
 
-fn load_game() -> Result<(Game, Vec<Object>), Box<dyn Error>> {
-    let mut json_save_state = String::new();
-    let mut file = File::open("savegame")?;
-    file.read_to_string(&mut json_save_state)?;
-    let result = serde_json::from_str::<(Game, Vec<Object>)>(&json_save_state)?;
-    Ok(result)
+/// list every save slot with a preview of what is in it
+fn choose_slot(header: &str, ui: &mut Ui) -> Option<usize> {
+    let options: Vec<String> = (0..SAVE_SLOTS)
+        .map(|slot| {
+            let preview = match save_summary(slot) {
+                Ok(summary) => format!(
+                    "level {}, dungeon {}, {} turns, {}",
+                    summary.character_level,
+                    summary.dungeon_level,
+                    summary.turns,
+                    format_timestamp(summary.saved_at)
+                ),
+                Err(SaveError::NotFound) => "empty".into(),
+                Err(SaveError::Incompatible { game_version, .. }) => format!("made by version {}", game_version),
+                Err(_) => "unreadable".into(),
+            };
+            format!("Slot {}: {}", slot + 1, preview)
+        })
+        .collect();
+    menu(header, &options, SLOT_SCREEN_WIDTH, ui)
 }
 
-pub fn msgbox(text: &str, width: i32, root: &mut Root) {
+pub fn msgbox(text: &str, width: i32, ui: &mut Ui) {
     let options: &[&str] = &[];
-    menu(text, options, width, root);
+    menu(text, options, width, ui);
 }
\ No newline at end of file
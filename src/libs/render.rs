@@ -1,66 +1,24 @@
-use tcod::console::*;
+use tcod::console::TextAlignment;
 use tcod::colors::*;
 
 use crate::predefs::constants::*;
-use crate::predefs::structs::{Tcod, Game,Object};
+use crate::predefs::structs::{Ui, GameState, Canvas, Objects, Palette, TileKind, Hazard};
+use crate::libs::canvas::height_rect;
 use crate::libs::make_map::*;
 
-pub fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recompute: bool){
-    if fov_recompute {
-        // recompute FOV if needed (the player moved or something)
-        let player = &objects[PLAYER];
-        tcod.fov
-        .compute_fov(player.x, player.y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
-    }    
-    //map render
-    for y in 0..MAP_HEIGHT {
-        for x in 0..MAP_WIDTH {
-            let visible = tcod.fov.is_in_fov(x, y);
-            let wall = game.map[x as usize][y as usize].block_sight;
-            let color = match (visible, wall) {
-                // outside of field of view:
-                (false, true) => COLOR_DARK_WALL,
-                (false, false) => COLOR_DARK_GROUND,
-                // inside fov:
-                (true, true) => COLOR_LIGHT_WALL,
-                (true, false) => COLOR_LIGHT_GROUND,
-            };
-            let explored = &mut game.map[x as usize][y as usize].explored;
-            if visible {
-                // since it's visible, explore it
-                *explored = true;
-            }
-            if *explored {
-                // show explored tiles only (any visible tile is explored already)
-                tcod.con
-                    .set_char_background(x, y, color, BackgroundFlag::Set);
-            }
-        }
-    }
-    
-    let mut to_draw: Vec<_> = objects
-    .iter()
-    .filter(|o| {
-        tcod.fov.is_in_fov(o.x, o.y)
-            || (o.always_visible && game.map[o.x as usize][o.y as usize].explored)
-    })
-    .collect();
-    // sort so that non-blocknig objects come first
-    to_draw.sort_by(|o1, o2| o1.blocks.cmp(&o2.blocks));
-    // draw the objects in the list
-    for object in &to_draw {
-        object.draw(&mut tcod.con);
-    }
+pub fn render_all(ui: &mut Ui, state: &GameState){
+    let GameState { game, objects, fov, .. } = state;
+    let camera = camera(objects);
+    draw_map(&mut ui.con, state, camera);
 
     // prepare to render the GUI panel
-    tcod.panel.set_default_background(BLACK);
-    tcod.panel.clear();
+    ui.panel.clear();
     
     // show the player's stats
     let hp = objects[PLAYER].fighter.map_or(0, |f| f.hp);
     let max_hp = objects[PLAYER].max_hp(game);
     render_bar(
-        &mut tcod.panel,
+        &mut ui.panel,
         1,
         1,
         BAR_WIDTH,
@@ -73,53 +31,121 @@ pub fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_reco
     // print the game messages, one line at a time
     let mut y = MSG_HEIGHT as i32;
     for &(ref msg, color) in game.messages.iter().rev() {
-        let msg_height = tcod.panel.get_height_rect(MSG_X, y, MSG_WIDTH, 0, msg);
+        let msg_height = height_rect(MSG_WIDTH, msg);
         y -= msg_height;
         if y < 0 {
             break;
         }
-        tcod.panel.set_default_foreground(color);
-        tcod.panel.print_rect(MSG_X, y, MSG_WIDTH, 0, msg);
+        ui.panel.print_rect(MSG_X, y, MSG_WIDTH, msg, color);
     }
-    tcod.panel.set_default_foreground(LIGHT_GREY);
-    tcod.panel.print_ex(
+    ui.panel.print(
         1,
         0,
-        BackgroundFlag::None,
+        &get_names_under_mouse(ui.mouse, camera, objects, fov),
+        LIGHT_GREY,
         TextAlignment::Left,
-        get_names_under_mouse(tcod.mouse, objects, &tcod.fov),
     );
-    tcod.panel.print_ex(
+    ui.panel.print(
         1,
         3,
-        BackgroundFlag::None,
+        &format!("Dungeon level: {}", game.dungeon_level),
+        LIGHT_GREY,
         TextAlignment::Left,
-        format!("Dungeon level: {}", game.dungeon_level),
     );
+    if game.confused_turns > 0 {
+        ui.panel.print(1, 4, "Confused", LIGHT_GREEN, TextAlignment::Left);
+    }
 
     // blit the contents of `panel` to the root console
-    blit(
-        &tcod.panel,
-        (0, 0),
-        (SCREEN_WIDTH, PANEL_HEIGHT),
-        &mut tcod.root,
-        (0, PANEL_Y),
-        1.0,
-        1.0,
-    );
+    ui.panel.blit(&mut ui.root, 0, PANEL_Y, 1.0);
+    ui.con.blit(&mut ui.root, 0, 0, 1.0);
+}
 
-    blit(
-        &tcod.con,
-        (0, 0),
-        (MAP_WIDTH, MAP_HEIGHT),
-        &mut tcod.root,
-        (0, 0),
-        1.0,
-        1.0,
-    );
+/// the top left corner of the part of the level on screen: the player is kept in the
+/// middle, except near the edges of the map, which stay put against the edges of the view
+pub fn camera(objects: &Objects) -> (i32, i32) {
+    let (x, y) = objects[PLAYER].pos();
+    (
+        (x - VIEW_WIDTH / 2).clamp(0, MAP_WIDTH - VIEW_WIDTH),
+        (y - VIEW_HEIGHT / 2).clamp(0, MAP_HEIGHT - VIEW_HEIGHT),
+    )
+}
+
+/// the tile under a spot on screen, if that spot shows the map at all
+pub fn screen_to_map(x: i32, y: i32, (camera_x, camera_y): (i32, i32)) -> Option<(i32, i32)> {
+    if (0..VIEW_WIDTH).contains(&x) && (0..VIEW_HEIGHT).contains(&y) {
+        Some((x + camera_x, y + camera_y))
+    } else {
+        None
+    }
 }
+
+/// the explored part of the level and what can be seen on it, as much of it as fits
+/// on `con` from `camera` on
+pub fn draw_map(con: &mut Canvas, state: &GameState, camera: (i32, i32)) {
+    let GameState { game, objects, fov, content, .. } = state;
+    let (camera_x, camera_y) = camera;
+    let palette = content.palette(game.theme.as_deref());
+    //map render
+    for y in camera_y..(camera_y + con.height).min(MAP_HEIGHT) {
+        for x in camera_x..(camera_x + con.width).min(MAP_WIDTH) {
+            let visible = fov.is_in_fov(x, y);
+            let tile = game.map[x as usize][y as usize];
+            let (screen_x, screen_y) = (x - camera_x, y - camera_y);
+            if tile.explored {
+                // show explored tiles only (any visible tile is explored already)
+                con.set_background(screen_x, screen_y, tile_color(tile.kind, visible, &palette));
+                let glyph = match tile.kind {
+                    TileKind::Door { open, .. } => Some((if open { '\'' } else { '+' }, COLOR_DOOR)),
+                    TileKind::Hazard(Hazard::Lava) => Some(('~', YELLOW)),
+                    TileKind::Hazard(Hazard::Chasm) => Some((':', DARK_GREY)),
+                    TileKind::Hazard(_) => Some(('~', LIGHT_SKY)),
+                    _ => None,
+                };
+                if let Some((ch, color)) = glyph {
+                    con.put_char(screen_x, screen_y, ch, color);
+                }
+            }
+        }
+    }
+    
+    let mut to_draw: Vec<_> = objects
+    .iter()
+    .map(|(_, o)| o)
+    .filter(|o| {
+        fov.is_in_fov(o.x, o.y)
+            || (o.always_visible && game.map[o.x as usize][o.y as usize].explored)
+    })
+    // traps nobody has found yet
+    .filter(|o| !o.trap.is_some_and(|trap| trap.hidden))
+    .collect();
+    // sort so that non-blocknig objects come first
+    to_draw.sort_by_key(|o| o.blocks);
+    // draw the objects in the list
+    for object in &to_draw {
+        object.draw(con, camera);
+    }
+}
+
+/// the background of a tile, in or out of the field of view
+fn tile_color(kind: TileKind, visible: bool, palette: &Palette) -> Color {
+    let hazard_color = |color: Color| if visible { color } else { color * 0.5 };
+    match (kind, visible) {
+        (TileKind::Wall | TileKind::Secret { .. }, false) => palette.dark_wall,
+        (TileKind::Wall | TileKind::Secret { .. }, true) => palette.light_wall,
+        (TileKind::Hazard(Hazard::ShallowWater), _) => hazard_color(COLOR_SHALLOW_WATER),
+        (TileKind::Hazard(Hazard::DeepWater), _) => hazard_color(COLOR_DEEP_WATER),
+        (TileKind::Hazard(Hazard::Lava), _) => hazard_color(COLOR_LAVA),
+        (TileKind::Hazard(Hazard::Chasm), _) => hazard_color(COLOR_CHASM),
+        // floor and doors
+        (_, false) => palette.dark_ground,
+        (_, true) => palette.light_ground,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn render_bar(
-    panel: &mut Offscreen,
+    panel: &mut Canvas,
     x: i32,
     y: i32,
     total_width: i32,
@@ -133,21 +159,18 @@ pub fn render_bar(
     let bar_width = (value as f32 / maximum as f32 * total_width as f32) as i32;
 
     // render the background first
-    panel.set_default_background(back_color);
-    panel.rect(x, y, total_width, 1, false, BackgroundFlag::Screen);
+    panel.fill_background(x, y, total_width, 1, back_color);
 
     // now render the bar on top
-    panel.set_default_background(bar_color);
     if bar_width > 0 {
-        panel.rect(x, y, bar_width, 1, false, BackgroundFlag::Screen);
+        panel.fill_background(x, y, bar_width, 1, bar_color);
     }
     // finally, some centered text with the values
-    panel.set_default_foreground(WHITE);
-    panel.print_ex(
+    panel.print(
         x + total_width / 2,
         y,
-        BackgroundFlag::None,
-        TextAlignment::Center,
         &format!("{}: {}/{}", name, value, maximum),
+        WHITE,
+        TextAlignment::Center,
     );
 }
\ No newline at end of file
@@ -0,0 +1,112 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tcod::input::KeyCode;
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::menu::{new_game, msgbox};
+use crate::libs::render::*;
+use crate::libs::save::user_data_dir;
+
+/// write the seed and every command of the run, so it can be reproduced exactly. every
+/// run gets its own file in the replays directory, next to the saved games; returns its path
+pub fn save_replay(game: &Game) -> Result<PathBuf, Box<dyn Error>> {
+    let seed = game.seed.ok_or("the seed of this run is unknown")?;
+    let replay = Replay {
+        game_version: env!("CARGO_PKG_VERSION").into(),
+        seed,
+        commands: game.commands.clone(),
+    };
+    let replay_data = serde_json::to_string(&replay)?;
+    let directory = user_data_dir().join("replays");
+    fs::create_dir_all(&directory)?;
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    let path = directory.join(format!("{}-{}.json", seed, time));
+    let mut file = File::create(&path)?;
+    file.write_all(replay_data.as_bytes())?;
+    Ok(path)
+}
+
+pub fn load_replay(path: &str) -> Result<Replay, Box<dyn Error>> {
+    let mut json_replay = String::new();
+    let mut file = File::open(path)?;
+    file.read_to_string(&mut json_replay)?;
+    let replay = serde_json::from_str::<Replay>(&json_replay)?;
+    if replay.game_version != env!("CARGO_PKG_VERSION") {
+        eprintln!(
+            "Warning: this replay was recorded with version {}, it may not play out the same.",
+            replay.game_version
+        );
+    }
+    Ok(replay)
+}
+
+/// play the whole run without any window, as fast as possible
+pub fn replay_headless(replay: &Replay, content: &Content) -> GameState {
+    let mut state = new_game(replay.seed, content);
+    for &command in &replay.commands {
+        state.step(command);
+    }
+    state
+}
+
+pub fn print_summary(state: &GameState) {
+    for (msg, _) in state.game.messages.iter() {
+        println!("{}", msg);
+    }
+    let player = &state.objects[PLAYER];
+    println!(
+        "\n{} commands, dungeon level {}, character level {}, {}.",
+        state.game.commands.len(),
+        state.game.dungeon_level,
+        player.level,
+        if player.alive { "alive" } else { "dead" }
+    );
+}
+
+/// draw the game once and show it
+pub fn print_screen(ui: &mut Ui, state: &GameState) {
+    render_all(ui, state);
+    ui.flush();
+}
+
+/// show the run turn by turn: space pauses, + and - change the speed, Escape stops
+pub fn replay_game(ui: &mut Ui, replay: &Replay, content: &Content, mut speed: u64) {
+    let mut state = new_game(replay.seed, content);
+    let mut commands = replay.commands.iter();
+    let mut paused = false;
+
+    while !ui.closed() {
+        ui.con.clear();
+
+        ui.poll();
+        match (ui.key.code, ui.key.printable) {
+            (KeyCode::Escape, _) => break,
+            (KeyCode::Spacebar, _) => paused = !paused,
+            (KeyCode::Text, '+') => speed /= 2,
+            (KeyCode::Text, '-') => speed = (speed * 2).clamp(1, MAX_REPLAY_DELAY),
+            _ => {}
+        }
+
+        render_all(ui, &state);
+        ui.flush();
+
+        if paused {
+            continue;
+        }
+        match commands.next() {
+            Some(&command) => {
+                state.step(command);
+            }
+            None => {
+                msgbox("\nEnd of the replay.\n", 24, ui);
+                break;
+            }
+        }
+        thread::sleep(Duration::from_millis(speed));
+    }
+}
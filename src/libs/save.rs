@@ -0,0 +1,719 @@
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{self, DeserializeOwned, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+
+#[derive(Serialize)]
+struct SaveFile<'a> {
+    header: SaveHeader,
+    game: &'a Game,
+    objects: &'a Objects,
+}
+
+#[derive(Deserialize)]
+struct LoadedSave {
+    game: Game,
+    objects: Objects,
+}
+
+/// the header of a save alone; the game that follows it is skipped over
+#[derive(Deserialize)]
+struct LoadedHeader {
+    header: SaveHeader,
+}
+
+type Migration = fn(Value) -> Result<Value, String>;
+
+/// binary saves start with these bytes, then 0 for a plain or 1 for a deflated payload
+const BINARY_MAGIC: &[u8] = b"ARBS";
+
+/// upgrades of older saves, in order: `MIGRATIONS[n]` turns format `n` into format `n + 1`
+const MIGRATIONS: &[Migration] = &[migrate_v0, migrate_v1, migrate_v2, migrate_v3, migrate_v4, migrate_v5, migrate_v6, migrate_v7, migrate_v8];
+
+#[cfg(test)]
+thread_local! {
+    // every test runs on a thread of its own, so each can keep its files apart
+    static TEST_DATA_DIR: std::cell::RefCell<Option<PathBuf>> = const { std::cell::RefCell::new(None) };
+}
+
+/// keep the files of the test running on this thread in `directory`, instead of the
+/// data directory of whoever runs the tests
+#[cfg(test)]
+pub fn use_test_data_dir(directory: PathBuf) {
+    TEST_DATA_DIR.with(|dir| *dir.borrow_mut() = Some(directory));
+}
+
+/// where saves are kept: the per-user data directory of the platform
+pub fn user_data_dir() -> PathBuf {
+    #[cfg(test)]
+    if let Some(directory) = TEST_DATA_DIR.with(|dir| dir.borrow().clone()) {
+        return directory;
+    }
+    let base = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join("a-robber")
+}
+
+fn slot_path(slot: usize) -> PathBuf {
+    user_data_dir().join(format!("slot{}.sav", slot + 1))
+}
+
+pub fn save_game(slot: usize, format: SaveFormat, game: &Game, objects: &Objects) -> Result<(), SaveError> {
+    if !objects[PLAYER].alive {
+        return Err(SaveError::Dead);
+    }
+    let save = SaveFile {
+        header: save_header(game, objects),
+        game,
+        objects,
+    };
+    let save_data = encode_save(&save, format)?;
+    fs::create_dir_all(user_data_dir())?;
+    // write next to the old save and swap them, so a crash never leaves half a save behind
+    let path = slot_path(slot);
+    let temp_path = path.with_extension("tmp");
+    let mut file = File::create(&temp_path)?;
+    file.write_all(&save_data)?;
+    file.sync_all()?;
+    fs::rename(temp_path, path)?;
+    Ok(())
+}
+
+fn save_header(game: &Game, objects: &Objects) -> SaveHeader {
+    let saved_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    SaveHeader {
+        format_version: SAVE_FORMAT_VERSION,
+        game_version: env!("CARGO_PKG_VERSION").into(),
+        summary: SaveSummary {
+            character_level: objects[PLAYER].level,
+            dungeon_level: game.dungeon_level,
+            turns: game.turns,
+            saved_at,
+        },
+    }
+}
+
+fn encode_save(save: &SaveFile, format: SaveFormat) -> Result<Vec<u8>, SaveError> {
+    let payload = match format {
+        SaveFormat::Json => return serde_json::to_vec(save).map_err(|e| SaveError::Corrupt(e.to_string())),
+        // named fields, so binary saves can be upgraded just like JSON ones
+        SaveFormat::Binary { .. } => rmp_serde::to_vec_named(save).map_err(|e| SaveError::Corrupt(e.to_string()))?,
+    };
+    let mut data = BINARY_MAGIC.to_vec();
+    if let SaveFormat::Binary { compressed: true } = format {
+        data.push(1);
+        let mut encoder = DeflateEncoder::new(data, Compression::best());
+        encoder.write_all(&payload)?;
+        Ok(encoder.finish()?)
+    } else {
+        data.push(0);
+        data.extend(payload);
+        Ok(data)
+    }
+}
+
+/// tell the formats apart by their first bytes: JSON saves never start with the magic
+fn decode_save<T: DeserializeOwned>(data: &[u8]) -> Result<T, SaveError> {
+    let save = match data.strip_prefix(BINARY_MAGIC) {
+        None => serde_json::from_slice(data).map_err(|e| e.to_string()),
+        Some([0, payload @ ..]) => rmp_serde::from_slice(payload).map_err(|e| e.to_string()),
+        Some([1, payload @ ..]) => {
+            let mut decoded = vec![];
+            DeflateDecoder::new(payload).read_to_end(&mut decoded)?;
+            rmp_serde::from_slice(&decoded).map_err(|e| e.to_string())
+        }
+        Some(_) => Err("unknown kind of binary save".into()),
+    };
+    save.map_err(SaveError::Corrupt)
+}
+
+pub fn load_game(slot: usize) -> Result<(Game, Objects), SaveError> {
+    let save = read_save(slot)?;
+    let result = serde_json::from_value::<LoadedSave>(save).map_err(|e| SaveError::Corrupt(e.to_string()))?;
+    // older versions saved dead characters too
+    if !result.objects[PLAYER].alive {
+        delete_save(slot)?;
+        return Err(SaveError::Dead);
+    }
+    Ok((result.game, result.objects))
+}
+
+/// what is in a slot, from the header alone: the game itself is skipped, not loaded.
+/// saves from before headers had a summary are loaded and upgraded to get one
+pub fn save_summary(slot: usize) -> Result<SaveSummary, SaveError> {
+    summary_of(&read_slot(slot)?)
+}
+
+fn summary_of(save_data: &[u8]) -> Result<SaveSummary, SaveError> {
+    match decode_save::<LoadedHeader>(save_data) {
+        Ok(LoadedHeader { header }) if header.format_version <= SAVE_FORMAT_VERSION => Ok(header.summary),
+        // let the upgrade tell apart newer and damaged saves
+        _ => {
+            let save = upgrade_save(decode_save(save_data)?)?;
+            serde_json::from_value(save["header"]["summary"].clone()).map_err(|e| SaveError::Corrupt(e.to_string()))
+        }
+    }
+}
+
+pub fn delete_save(slot: usize) -> Result<(), SaveError> {
+    fs::remove_file(slot_path(slot))?;
+    Ok(())
+}
+
+/// the first slot nobody is using, if any
+pub fn free_slot() -> Option<usize> {
+    (0..SAVE_SLOTS).find(|&slot| !slot_path(slot).exists())
+}
+
+/// games used to be saved to `savegame` in the working directory; move it into a slot
+pub fn import_legacy_save() {
+    let legacy = PathBuf::from("savegame");
+    if let (true, Some(slot)) = (legacy.is_file(), free_slot()) {
+        if fs::create_dir_all(user_data_dir()).is_ok() && fs::copy(&legacy, slot_path(slot)).is_ok() {
+            let _ = fs::remove_file(legacy);
+        }
+    }
+}
+
+/// read a save and bring it up to the current format
+fn read_save(slot: usize) -> Result<Value, SaveError> {
+    upgrade_save(decode_save(&read_slot(slot)?)?)
+}
+
+fn read_slot(slot: usize) -> Result<Vec<u8>, SaveError> {
+    let mut save_data = vec![];
+    let mut file = File::open(slot_path(slot))?;
+    file.read_to_end(&mut save_data)?;
+    Ok(save_data)
+}
+
+/// run every migration from the format of the save to the current one
+fn upgrade_save(mut save: Value) -> Result<Value, SaveError> {
+    let mut version = format_version(&save)?;
+    if version > SAVE_FORMAT_VERSION {
+        let game_version = save["header"]["game_version"].as_str().unwrap_or("unknown");
+        return Err(SaveError::Incompatible {
+            format_version: version,
+            game_version: game_version.into(),
+        });
+    }
+    while version < SAVE_FORMAT_VERSION {
+        save = MIGRATIONS[version as usize](save).map_err(SaveError::Corrupt)?;
+        version += 1;
+    }
+    Ok(save)
+}
+
+/// a map squeezed into one byte per tile, for binary saves
+#[derive(Serialize, Deserialize)]
+struct PackedMap {
+    width: usize,
+    height: usize,
+    /// column by column, like `Map` itself
+    tiles: Vec<u8>,
+}
+
+// the top bit of each byte says if the tile was explored, the rest is what it is made of
+const TILE_EXPLORED: u8 = 1 << 7;
+
+fn kind_code(kind: TileKind) -> u8 {
+    match kind {
+        TileKind::Floor => 0,
+        TileKind::Wall => 1,
+        TileKind::Door { open: false, locked: false } => 2,
+        TileKind::Door { open: true, .. } => 3,
+        TileKind::Door { open: false, locked: true } => 4,
+        TileKind::Hazard(Hazard::ShallowWater) => 5,
+        TileKind::Hazard(Hazard::DeepWater) => 6,
+        TileKind::Hazard(Hazard::Lava) => 7,
+        TileKind::Hazard(Hazard::Chasm) => 8,
+        TileKind::Secret { door: true } => 9,
+        TileKind::Secret { door: false } => 10,
+    }
+}
+
+fn code_kind(code: u8) -> Option<TileKind> {
+    match code {
+        0 => Some(TileKind::Floor),
+        1 => Some(TileKind::Wall),
+        2 => Some(TileKind::Door { open: false, locked: false }),
+        3 => Some(TileKind::Door { open: true, locked: false }),
+        4 => Some(TileKind::Door { open: false, locked: true }),
+        5 => Some(TileKind::Hazard(Hazard::ShallowWater)),
+        6 => Some(TileKind::Hazard(Hazard::DeepWater)),
+        7 => Some(TileKind::Hazard(Hazard::Lava)),
+        8 => Some(TileKind::Hazard(Hazard::Chasm)),
+        9 => Some(TileKind::Secret { door: true }),
+        10 => Some(TileKind::Secret { door: false }),
+        _ => None,
+    }
+}
+
+impl PackedMap {
+    fn pack(map: &Map) -> Self {
+        let flag = |set: bool, bit: u8| if set { bit } else { 0 };
+        PackedMap {
+            width: map.len(),
+            height: map.first().map_or(0, |column| column.len()),
+            tiles: map
+                .iter()
+                .flatten()
+                .map(|tile| kind_code(tile.kind) | flag(tile.explored, TILE_EXPLORED))
+                .collect(),
+        }
+    }
+
+    fn unpack(self) -> Result<Map, String> {
+        if self.width * self.height != self.tiles.len() || self.height == 0 {
+            return Err(format!("a {}x{} map can't have {} tiles", self.width, self.height, self.tiles.len()));
+        }
+        let unpack_tile = |&bits: &u8| {
+            let kind = code_kind(bits & !TILE_EXPLORED).ok_or(format!("unknown tile kind {}", bits & !TILE_EXPLORED))?;
+            Ok(Tile {
+                kind,
+                explored: bits & TILE_EXPLORED != 0,
+            })
+        };
+        self.tiles
+            .chunks(self.height)
+            .map(|column| column.iter().map(unpack_tile).collect())
+            .collect()
+    }
+}
+
+/// JSON keeps the map as columns of tiles, binary saves pack it
+pub fn serialize_map<S: Serializer>(map: &Map, serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        map.serialize(serializer)
+    } else {
+        PackedMap::pack(map).serialize(serializer)
+    }
+}
+
+/// accepts both forms, whatever the deserializer: saves go through a `Value` to be upgraded
+pub fn deserialize_map<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Map, D::Error> {
+    deserializer.deserialize_any(MapVisitor)
+}
+
+struct MapVisitor;
+
+impl<'de> Visitor<'de> for MapVisitor {
+    type Value = Map;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "columns of tiles or a packed map")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Map, A::Error> {
+        Map::deserialize(SeqAccessDeserializer::new(seq))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Map, A::Error> {
+        PackedMap::deserialize(MapAccessDeserializer::new(map))?
+            .unpack()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// a date like "2021-03-27 18:05" (UTC)
+pub fn format_timestamp(seconds: u64) -> String {
+    if seconds == 0 {
+        return "unknown date".into();
+    }
+    // days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let days = (seconds / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    let minutes = seconds % 86_400 / 60;
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, minutes / 60, minutes % 60)
+}
+
+fn format_version(save: &Value) -> Result<u32, SaveError> {
+    if save.is_array() {
+        // saves from before there was a header
+        return Ok(0);
+    }
+    save["header"]["format_version"]
+        .as_u64()
+        .map(|version| version as u32)
+        .ok_or_else(|| SaveError::Corrupt("missing save header".into()))
+}
+
+/// 0.1.0 saved a bare `(game, objects)` pair, with a plain list of objects and
+/// item kinds whose strength was hard-coded
+fn migrate_v0(save: Value) -> Result<Value, String> {
+    let (mut game, objects) = match save {
+        Value::Array(mut pair) if pair.len() == 2 => {
+            let objects = pair.pop().unwrap();
+            (pair.pop().unwrap(), objects)
+        }
+        _ => return Err("expected a game and its objects".into()),
+    };
+    let objects = objects.as_array().ok_or("expected a list of objects")?;
+
+    // the seed of these runs was never recorded, so they can't be replayed
+    game["seed"] = Value::Null;
+    game["rng"] = serde_json::to_value(GameRng::new(0)).map_err(|e| e.to_string())?;
+    game["commands"] = json!([]);
+    if let Some(inventory) = game["inventory"].as_array_mut() {
+        inventory.iter_mut().for_each(migrate_v0_item);
+    }
+    let entries: Vec<Value> = objects
+        .iter()
+        .cloned()
+        .map(|mut object| {
+            migrate_v0_item(&mut object);
+            json!({ "generation": 0, "object": object })
+        })
+        .collect();
+
+    Ok(json!({
+        "header": { "format_version": 1, "game_version": "0.1.0" },
+        "game": game,
+        "objects": { "entries": entries, "free": [] },
+    }))
+}
+
+/// format 1 had no turn counter and nothing to preview a slot with
+fn migrate_v1(mut save: Value) -> Result<Value, String> {
+    save["game"]["turns"] = json!(0);
+    // the player is always the first object
+    let character_level = save["objects"]["entries"][0]["object"]["level"].clone();
+    let dungeon_level = save["game"]["dungeon_level"].clone();
+    save["header"]["format_version"] = json!(2);
+    save["header"]["summary"] = json!({
+        "character_level": character_level,
+        "dungeon_level": dungeon_level,
+        "turns": 0,
+        "saved_at": 0,
+    });
+    Ok(save)
+}
+
+/// format 2 tiles were only blocked or not: walls and floor, no doors. the map can be
+/// columns of tiles (JSON) or packed, with a blocked bit and an explored bit
+fn migrate_v2(mut save: Value) -> Result<Value, String> {
+    let map = &mut save["game"]["map"];
+    if let Some(columns) = map.as_array_mut() {
+        for tile in columns.iter_mut().filter_map(Value::as_array_mut).flatten() {
+            let kind = if tile["blocked"].as_bool().ok_or("a tile without \"blocked\"")? { "Wall" } else { "Floor" };
+            *tile = json!({ "kind": kind, "explored": tile["explored"].clone() });
+        }
+    } else if let Some(tiles) = map["tiles"].as_array_mut() {
+        for tile in tiles.iter_mut() {
+            let bits = tile.as_u64().ok_or("a packed tile that isn't a number")? as u8;
+            let kind = kind_code(if bits & 1 != 0 { TileKind::Wall } else { TileKind::Floor });
+            let explored = if bits & (1 << 2) != 0 { TILE_EXPLORED } else { 0 };
+            *tile = json!(kind | explored);
+        }
+    } else {
+        return Err("the map is neither columns of tiles nor packed".into());
+    }
+    save["header"]["format_version"] = json!(3);
+    Ok(save)
+}
+
+/// format 3 had no hazards, so nothing was immune to them
+fn migrate_v3(mut save: Value) -> Result<Value, String> {
+    let entries = save["objects"]["entries"].as_array_mut().ok_or("expected a list of objects")?;
+    for object in entries.iter_mut().map(|entry| &mut entry["object"]).filter(|object| object.is_object()) {
+        object["immune"] = json!([]);
+    }
+    if let Some(inventory) = save["game"]["inventory"].as_array_mut() {
+        inventory.iter_mut().for_each(|item| item["immune"] = json!([]));
+    }
+    save["header"]["format_version"] = json!(4);
+    Ok(save)
+}
+
+/// format 4 had no traps, and so no confused player either
+fn migrate_v4(mut save: Value) -> Result<Value, String> {
+    let entries = save["objects"]["entries"].as_array_mut().ok_or("expected a list of objects")?;
+    for object in entries.iter_mut().map(|entry| &mut entry["object"]).filter(|object| object.is_object()) {
+        object["trap"] = Value::Null;
+    }
+    if let Some(inventory) = save["game"]["inventory"].as_array_mut() {
+        inventory.iter_mut().for_each(|item| item["trap"] = Value::Null);
+    }
+    save["game"]["confused_turns"] = json!(0);
+    save["header"]["format_version"] = json!(5);
+    Ok(save)
+}
+
+/// format 5 kept only the level the player was on, and its stairs only went down.
+/// the levels above are gone, so there is no way back up from a level loaded this way
+fn migrate_v5(mut save: Value) -> Result<Value, String> {
+    let entries = save["objects"]["entries"].as_array_mut().ok_or("expected a list of objects")?;
+    for object in entries.iter_mut().map(|entry| &mut entry["object"]).filter(|object| object["name"] == "stairs") {
+        object["name"] = json!("stairs down");
+        object["char"] = json!(">");
+    }
+    save["game"]["levels"] = json!([]);
+    save["header"]["format_version"] = json!(6);
+    Ok(save)
+}
+
+/// format 6 levels were as big as the screen. they get solid rock around the right and
+/// bottom edges to make them as big as levels are now
+fn migrate_v6(mut save: Value) -> Result<Value, String> {
+    grow_map(&mut save["game"]["map"])?;
+    if let Some(levels) = save["game"]["levels"].as_array_mut() {
+        for level in levels.iter_mut() {
+            grow_map(&mut level["map"])?;
+        }
+    }
+    save["header"]["format_version"] = json!(7);
+    Ok(save)
+}
+
+/// format 7 levels had no themes, and keep the colours they had
+fn migrate_v7(mut save: Value) -> Result<Value, String> {
+    save["game"]["theme"] = Value::Null;
+    if let Some(levels) = save["game"]["levels"].as_array_mut() {
+        levels.iter_mut().for_each(|level| level["theme"] = Value::Null);
+    }
+    save["header"]["format_version"] = json!(8);
+    Ok(save)
+}
+
+/// format 9 runs may have no seed, which older versions can't read; every format 8
+/// run has one, so only the version changes
+fn migrate_v8(mut save: Value) -> Result<Value, String> {
+    save["header"]["format_version"] = json!(9);
+    Ok(save)
+}
+
+/// pad a map, packed or not, to MAP_WIDTH by MAP_HEIGHT with walls. it is written back
+/// as columns of tiles, which binary saves read as well
+fn grow_map(map: &mut Value) -> Result<(), String> {
+    let mut tiles = deserialize_map(map.take()).map_err(|e| e.to_string())?;
+    tiles.resize(MAP_WIDTH as usize, vec![]);
+    for column in tiles.iter_mut() {
+        column.resize(MAP_HEIGHT as usize, Tile::wall());
+    }
+    *map = serialize_map(&tiles, serde_json::value::Serializer).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn migrate_v0_item(object: &mut Value) {
+    // the values 0.1.0 had in its constants
+    let effect = match object["item"].as_str() {
+        Some("Heal") => json!({ "Heal": { "amount": 40 } }),
+        Some("ScrollLightning") => json!({ "Lightning": { "damage": 40, "range": 5 } }),
+        Some("ScrollConfusion") => json!({ "Confusion": { "range": 8, "turns": 10 } }),
+        Some("ScrollFireball") => json!({ "Fireball": { "damage": 25, "radius": 3 } }),
+        // swords and shields
+        Some(_) => json!("Equipment"),
+        None => return,
+    };
+    object["item"] = effect;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::content::load_content;
+    use crate::libs::menu::new_game;
+
+    const FORMATS: [SaveFormat; 3] = [
+        SaveFormat::Json,
+        SaveFormat::Binary { compressed: false },
+        SaveFormat::Binary { compressed: true },
+    ];
+
+    /// a game a few turns in, always the same, with a level left behind
+    fn played_game() -> GameState {
+        let content = load_content().expect("the game data loads");
+        let mut state = new_game(7, &content);
+        let walk = |state: &mut GameState| {
+            for &(dx, dy) in [(1, 0), (0, 1), (-1, 0), (0, -1), (1, 1)].iter().cycle().take(30) {
+                state.step(PlayerCommand::Move { dx, dy });
+            }
+            state.step(PlayerCommand::Search);
+        };
+        walk(&mut state);
+        let (x, y) = state
+            .objects
+            .iter()
+            .find(|(_, object)| object.name == "stairs down")
+            .map(|(_, object)| object.pos())
+            .unwrap();
+        state.objects[PLAYER].set_pos(x, y);
+        state.step(PlayerCommand::Descend);
+        walk(&mut state);
+        assert_eq!(state.game.levels.len(), 1);
+        state
+    }
+
+    fn encode(state: &GameState, format: SaveFormat) -> Vec<u8> {
+        let save = SaveFile {
+            header: save_header(&state.game, &state.objects),
+            game: &state.game,
+            objects: &state.objects,
+        };
+        encode_save(&save, format).unwrap()
+    }
+
+    #[test]
+    fn summary_comes_from_the_header() {
+        let state = played_game();
+        for &format in FORMATS.iter() {
+            let summary = summary_of(&encode(&state, format)).unwrap();
+            assert_eq!(summary.character_level, state.objects[PLAYER].level);
+            assert_eq!(summary.dungeon_level, state.game.dungeon_level);
+            assert_eq!(summary.turns, state.game.turns);
+        }
+    }
+
+    #[test]
+    fn newer_saves_are_refused() {
+        let state = played_game();
+        let mut save: Value = serde_json::from_slice(&encode(&state, SaveFormat::Json)).unwrap();
+        save["header"]["format_version"] = json!(SAVE_FORMAT_VERSION + 1);
+        save["header"]["game_version"] = json!("9.9.9");
+        let save_data = serde_json::to_vec(&save).unwrap();
+        match summary_of(&save_data) {
+            Err(SaveError::Incompatible { format_version, game_version }) => {
+                assert_eq!(format_version, SAVE_FORMAT_VERSION + 1);
+                assert_eq!(game_version, "9.9.9");
+            }
+            other => panic!("expected an incompatible save, got {:?}", other),
+        }
+    }
+
+    fn load(save_data: &[u8]) -> LoadedSave {
+        let save = upgrade_save(decode_save(save_data).unwrap()).unwrap();
+        serde_json::from_value(save).unwrap()
+    }
+
+    #[test]
+    fn every_format_loads_the_same_game() {
+        let state = played_game();
+        let game = serde_json::to_value(&state.game).unwrap();
+        let objects = serde_json::to_value(&state.objects).unwrap();
+        let sizes: Vec<usize> = FORMATS
+            .iter()
+            .map(|&format| {
+                let save_data = encode(&state, format);
+                let loaded = load(&save_data);
+                assert_eq!(serde_json::to_value(&loaded.game).unwrap(), game, "{:?}", format);
+                assert_eq!(serde_json::to_value(&loaded.objects).unwrap(), objects, "{:?}", format);
+                save_data.len()
+            })
+            .collect();
+        // each format is smaller than the one before
+        assert!(sizes.windows(2).all(|pair| pair[1] < pair[0]), "{:?}", sizes);
+    }
+
+    #[test]
+    fn loaded_games_play_on_the_same() {
+        let mut state = played_game();
+        let LoadedSave { game, objects } = load(&encode(&state, SaveFormat::Binary { compressed: true }));
+        let mut loaded = GameState::new(game, objects, state.content.clone());
+        for &command in [PlayerCommand::Move { dx: -1, dy: 0 }, PlayerCommand::Wait, PlayerCommand::Search].iter().cycle().take(30) {
+            assert_eq!(state.step(command), loaded.step(command));
+        }
+        assert_eq!(state.objects[PLAYER].pos(), loaded.objects[PLAYER].pos());
+    }
+
+    #[test]
+    fn packed_maps_keep_every_tile() {
+        let kinds = [
+            TileKind::Floor,
+            TileKind::Wall,
+            TileKind::Door { open: false, locked: false },
+            TileKind::Door { open: true, locked: false },
+            TileKind::Door { open: false, locked: true },
+            TileKind::Hazard(Hazard::ShallowWater),
+            TileKind::Hazard(Hazard::DeepWater),
+            TileKind::Hazard(Hazard::Lava),
+            TileKind::Hazard(Hazard::Chasm),
+            TileKind::Secret { door: true },
+            TileKind::Secret { door: false },
+        ];
+        // every kind, explored and not, in a column of its own
+        let tiles: Vec<Tile> = kinds
+            .iter()
+            .flat_map(|&kind| vec![Tile { kind, explored: false }, Tile { kind, explored: true }])
+            .collect();
+        let map: Map = tiles.chunks(2).map(|column| column.to_vec()).collect();
+        let unpacked = PackedMap::pack(&map).unpack().unwrap();
+        let describe = |map: &Map| -> Vec<(TileKind, bool)> {
+            map.iter().flatten().map(|tile| (tile.kind, tile.explored)).collect()
+        };
+        assert_eq!(unpacked.len(), map.len());
+        assert_eq!(describe(&unpacked), describe(&map));
+    }
+
+    #[test]
+    fn broken_packed_maps_are_refused() {
+        let too_few = PackedMap { width: 2, height: 2, tiles: vec![0, 1, 0] };
+        assert!(too_few.unpack().is_err());
+        let unknown = PackedMap { width: 1, height: 1, tiles: vec![42] };
+        assert!(unknown.unpack().is_err());
+    }
+
+    #[test]
+    fn saves_from_0_1_0_are_upgraded() {
+        let save_data = include_bytes!("../../tests/fixtures/savegame-0.1.0.json");
+        let original: Value = serde_json::from_slice(save_data).unwrap();
+        let old_map = original[0]["map"].as_array().unwrap();
+
+        let summary = summary_of(save_data).unwrap();
+        assert_eq!((summary.character_level, summary.dungeon_level, summary.turns), (1, 1, 0));
+
+        let LoadedSave { game, objects } = load(save_data);
+        assert_eq!((game.map.len(), game.map[0].len()), (MAP_WIDTH as usize, MAP_HEIGHT as usize));
+        for (x, column) in game.map.iter().enumerate() {
+            for (y, tile) in column.iter().enumerate() {
+                // the old level is where it was, with solid rock around it
+                let old = old_map.get(x).and_then(|column| column.get(y));
+                let blocked = old.is_none_or(|old| old["blocked"] == true);
+                let explored = old.is_some_and(|old| old["explored"] == true);
+                let kind = if blocked { TileKind::Wall } else { TileKind::Floor };
+                assert_eq!((tile.kind, tile.explored), (kind, explored), "tile {:?}", (x, y));
+            }
+        }
+        assert_eq!(game.dungeon_level, 1);
+        assert_eq!(game.theme, None);
+        assert_eq!(game.seed, None);
+        assert!(game.levels.is_empty());
+        assert_eq!(game.inventory[1].item, Some(Item::Heal { amount: 40 }));
+        assert_eq!(game.inventory[0].item, Some(Item::Equipment));
+        assert_eq!(objects.iter().count(), original[1].as_array().unwrap().len());
+        assert!(objects.iter().any(|(_, object)| object.name == "stairs down" && object.char == '>'));
+        assert_eq!(objects[PLAYER].fighter.unwrap().hp, 83);
+
+        // and it can be played on
+        let content = load_content().expect("the game data loads");
+        let mut state = GameState::new(game, objects, content);
+        for _ in 0..10 {
+            state.step(PlayerCommand::Wait);
+        }
+        assert_eq!(state.game.turns, 10);
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashSet;
+use rand::Rng;
+
+use tcod::colors::*;
+use tcod::map::Map as FovMap;
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::make_map::from_dungeon_level;
+use crate::libs::validate::{check_map, is_corridor};
+
+/// hide some of the doors and corridors as secrets, more of them the deeper you go.
+/// only ways you don't need are hidden: one that would cut off any of the level from
+/// the start is left as it was
+pub fn place_secrets(map: &mut Map, objects: &Objects, start: (i32, i32), stairs: (i32, i32), level: u32, rng: &mut GameRng) {
+    let tries = from_dungeon_level(
+        &[
+            Transition { level: 1, value: 2 },
+            Transition { level: 3, value: 4 },
+            Transition { level: 6, value: 6 },
+        ],
+        level,
+    );
+    let mut keep: HashSet<(i32, i32)> = objects
+        .iter()
+        .filter(|&(id, _)| id != PLAYER)
+        .map(|(_, object)| object.pos())
+        .collect();
+    keep.insert(start);
+    keep.insert(stairs);
+    // doors, and the narrow bits of tunnel between rooms
+    let free: Vec<(i32, i32)> = (0..MAP_WIDTH)
+        .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)))
+        .filter(|pos| !keep.contains(pos))
+        .collect();
+    let doors: Vec<(i32, i32)> = free
+        .iter()
+        .copied()
+        .filter(|&(x, y)| matches!(map[x as usize][y as usize].kind, TileKind::Door { .. }))
+        .collect();
+    let passages: Vec<(i32, i32)> = free
+        .iter()
+        .copied()
+        .filter(|&(x, y)| map[x as usize][y as usize].kind == TileKind::Floor && is_corridor(x, y, map))
+        .collect();
+
+    for _ in 0..tries {
+        // there are many more bits of tunnel than doors, so each gets half the tries
+        let door = passages.is_empty() || (!doors.is_empty() && rng.gen());
+        let candidates = if door { &doors } else { &passages };
+        if candidates.is_empty() {
+            return;
+        }
+        let (x, y) = candidates[rng.gen_range(0, candidates.len())];
+        let tile = map[x as usize][y as usize];
+        if let TileKind::Secret { .. } = tile.kind {
+            // already hidden
+            continue;
+        }
+        map[x as usize][y as usize].kind = TileKind::Secret { door };
+        if check_map(map, start, stairs).is_err() {
+            map[x as usize][y as usize] = tile;
+        }
+    }
+}
+
+/// the player may notice secrets right next to them, every turn
+pub fn spot_secrets(fov: &FovMap, game: &mut Game, objects: &Objects) {
+    find_secrets(1, SECRET_SPOT_CHANCE, fov, game, objects);
+}
+
+/// give every secret in sight within `radius` of the player a `chance` to be found;
+/// returns how many were
+pub fn find_secrets(radius: i32, chance: f32, fov: &FovMap, game: &mut Game, objects: &Objects) -> u32 {
+    let (player_x, player_y) = objects[PLAYER].pos();
+    let mut found = 0;
+    for x in (player_x - radius).max(0)..=(player_x + radius).min(MAP_WIDTH - 1) {
+        for y in (player_y - radius).max(0)..=(player_y + radius).min(MAP_HEIGHT - 1) {
+            let tile = &mut game.map[x as usize][y as usize];
+            if let TileKind::Secret { door } = tile.kind {
+                if fov.is_in_fov(x, y) && game.rng.gen::<f32>() < chance {
+                    tile.kind = if door { TileKind::Door { open: false, locked: false } } else { TileKind::Floor };
+                    let what = if door { "secret door" } else { "secret passage" };
+                    game.messages.add(format!("You find a {}!", what), LIGHT_CYAN);
+                    found += 1;
+                }
+            }
+        }
+    }
+    found
+}
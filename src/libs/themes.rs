@@ -0,0 +1,57 @@
+use rand::distributions::Weighted;
+
+use tcod::colors::*;
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::make_map::{from_dungeon_level, pick_weighted};
+
+/// pick the theme of a new level from the ones found this deep
+pub fn choose_theme<'a>(level: u32, rng: &mut GameRng, content: &'a Content) -> &'a ThemeDef {
+    let theme_chances = &mut content
+        .themes
+        .iter()
+        .enumerate()
+        .map(|(index, theme)| Weighted {
+            weight: from_dungeon_level(&theme.spawn, level),
+            item: index,
+        })
+        .collect::<Vec<_>>();
+    &content.themes[pick_weighted(theme_chances, rng)]
+}
+
+/// what the player notices on first getting to a level: what kind of place it is,
+/// and a hint if there is a guarded vault or treasure on it, or anything else out of
+/// the ordinary for its depth
+pub fn announce_level(level: u32, theme: &ThemeDef, vault: Option<&VaultDef>, objects: &Objects, game: &mut Game, content: &Content) {
+    game.messages.add(theme.arrival.clone(), LIGHT_VIOLET);
+    let guarded = vault.is_some_and(|vault| {
+        let monsters = vault.legend.iter().filter(|(_, id)| content.monster(id).is_some()).map(|(&ch, _)| ch);
+        vault.map.iter().any(|row| row.contains('M') || monsters.clone().any(|ch| row.contains(ch)))
+    });
+    let treasure = vault.is_some_and(|vault| vault.map.iter().any(|row| row.contains('$')));
+    // too deep for this level, at least usually
+    let out_of_depth = |table: &[Transition]| from_dungeon_level(table, level) == 0;
+    let on_level = || objects.iter().filter(|&(id, _)| id != PLAYER).map(|(_, object)| object);
+
+    let danger = guarded || on_level().any(|object| {
+        object.fighter.is_some()
+            && content
+                .monsters
+                .iter()
+                .any(|monster| monster.name == object.name && out_of_depth(theme.monster_spawn(monster)))
+    });
+    let wealth = treasure || on_level().any(|object| {
+        object.item.is_some()
+            && content
+                .items
+                .iter()
+                .any(|item| item.name == object.name && !item.spawn.is_empty() && out_of_depth(theme.item_spawn(item)))
+    });
+    if danger {
+        game.messages.add("You feel something dangerous is lurking here.", RED);
+    }
+    if wealth {
+        game.messages.add("You sense great wealth here.", GOLD);
+    }
+}
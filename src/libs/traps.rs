@@ -0,0 +1,152 @@
+use rand::Rng;
+
+use tcod::colors::*;
+use tcod::map::Map as FovMap;
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::ai::confuse;
+use crate::libs::make_map::random_free_floor;
+use crate::libs::secrets::find_secrets;
+
+/// the trap at a spot, if there is one
+pub fn trap_at(x: i32, y: i32, objects: &Objects) -> Option<EntityId> {
+    objects
+        .iter()
+        .find(|(_, object)| object.pos() == (x, y) && object.trap.is_some())
+        .map(|(id, _)| id)
+}
+
+/// set off a trap on `victim_id`, which is usually standing on it. the player hears
+/// about it (and finds the trap) only when it can be seen going off
+pub fn spring_trap(trap_id: EntityId, victim_id: EntityId, fov: &FovMap, game: &mut Game, objects: &mut Objects) {
+    let effect = match objects[trap_id].trap {
+        Some(trap) => trap.effect,
+        None => return,
+    };
+    let (x, y) = objects[trap_id].pos();
+    let seen = victim_id == PLAYER || fov.is_in_fov(x, y);
+    if seen {
+        objects[trap_id].trap = Some(Trap { effect, hidden: false });
+    }
+    let player = victim_id == PLAYER;
+    let victim = objects[victim_id].name.clone();
+    let tell = |game: &mut Game, to_player: String, to_others: String| {
+        if seen {
+            game.messages.add(if player { to_player } else { to_others }, ORANGE);
+        }
+    };
+
+    match effect {
+        TrapEffect::Dart { damage } => {
+            tell(
+                game,
+                format!("A dart shoots out and hits you for {} hit points.", damage),
+                format!("A dart shoots out and hits the {} for {} hit points.", victim, damage),
+            );
+            objects[victim_id].take_damage(damage, game);
+        }
+        TrapEffect::Teleport => {
+            tell(game, "You are whisked away!".into(), format!("The {} vanishes!", victim));
+            // somewhere free on the level; if there's nowhere, it stays put
+            if let Some((to_x, to_y)) = random_free_floor(&game.map, objects, &mut game.rng) {
+                objects[victim_id].set_pos(to_x, to_y);
+            }
+        }
+        TrapEffect::Confusion { turns } => {
+            tell(
+                game,
+                "A cloud of gas bursts out, and your head starts to spin!".into(),
+                format!("A cloud of gas bursts out, and the {} starts to stumble around!", victim),
+            );
+            if player {
+                game.confused_turns = turns;
+            } else {
+                confuse(victim_id, turns, objects);
+            }
+        }
+        TrapEffect::Alarm { radius } => {
+            // loud enough to be heard from anywhere
+            game.messages.add("An alarm rings out, loud enough to wake the dead!", ORANGE);
+            for (_, monster) in objects.iter_mut() {
+                if monster.distance(x, y) <= radius as f32 && monster.ai == Some(Ai::Basic) {
+                    monster.ai = Some(Ai::Alerted { previous_ai: Box::new(Ai::Basic), x, y });
+                }
+            }
+        }
+        TrapEffect::Trapdoor => {
+            if objects[victim_id].pos() == (x, y) {
+                tell(game, "The floor gives way under you!".into(), format!("The floor gives way under the {}!", victim));
+            } else if seen {
+                game.messages.add("A trapdoor swings open.", ORANGE);
+            }
+            // whatever is on it falls through, like into any other chasm
+            game.map[x as usize][y as usize] = Tile::hazard(Hazard::Chasm);
+            objects.remove(trap_id);
+        }
+    }
+}
+
+/// the player may notice traps right next to them, every turn
+pub fn spot_traps(fov: &FovMap, game: &mut Game, objects: &mut Objects) {
+    find_traps(1, TRAP_SPOT_CHANCE, fov, game, objects);
+}
+
+/// look around carefully for hidden traps and secret doors, which takes a turn
+pub fn search(fov: &FovMap, game: &mut Game, objects: &mut Objects) -> PlayerAction {
+    let traps = find_traps(SEARCH_RADIUS, SEARCH_CHANCE, fov, game, objects);
+    let secrets = find_secrets(SEARCH_RADIUS, SECRET_SEARCH_CHANCE, fov, game, objects);
+    if traps + secrets == 0 {
+        game.messages.add("You search around, but find nothing.", WHITE);
+    }
+    PlayerAction::TookTurn
+}
+
+/// give every hidden trap in sight within `radius` of the player a `chance` to be
+/// found; returns how many were
+fn find_traps(radius: i32, chance: f32, fov: &FovMap, game: &mut Game, objects: &mut Objects) -> u32 {
+    let (player_x, player_y) = objects[PLAYER].pos();
+    let mut found = 0;
+    for (_, object) in objects.iter_mut() {
+        let near = (object.x - player_x).abs() <= radius && (object.y - player_y).abs() <= radius;
+        if let Some(trap) = object.trap.as_mut().filter(|trap| trap.hidden) {
+            if near && fov.is_in_fov(object.x, object.y) && game.rng.gen::<f32>() < chance {
+                trap.hidden = false;
+                game.messages.add(format!("You find a {}!", object.name), LIGHT_CYAN);
+                found += 1;
+            }
+        }
+    }
+    found
+}
+
+/// try to take apart a trap the player knows about, next to them or under their feet
+pub fn disarm(fov: &FovMap, game: &mut Game, objects: &mut Objects) -> PlayerAction {
+    let (player_x, player_y) = objects[PLAYER].pos();
+    let trap_id = objects
+        .iter()
+        .find(|(_, object)| {
+            object.trap.is_some_and(|trap| !trap.hidden)
+                && (object.x - player_x).abs() <= 1
+                && (object.y - player_y).abs() <= 1
+        })
+        .map(|(id, _)| id);
+    let trap_id = match trap_id {
+        Some(trap_id) => trap_id,
+        None => {
+            game.messages.add("There is no trap you know of next to you.", WHITE);
+            return PlayerAction::DidntTakeTurn;
+        }
+    };
+    let name = objects[trap_id].name.clone();
+    if game.rng.gen::<f32>() < DISARM_CHANCE {
+        game.messages.add(format!("You disarm the {}.", name), LIGHT_GREEN);
+        objects.remove(trap_id);
+    } else if game.rng.gen::<f32>() < DISARM_SPRING_CHANCE {
+        game.messages.add(format!("You fumble with the {}, and set it off!", name), RED);
+        spring_trap(trap_id, PLAYER, fov, game, objects);
+    } else {
+        game.messages.add(format!("You fail to disarm the {}.", name), WHITE);
+    }
+    PlayerAction::TookTurn
+}
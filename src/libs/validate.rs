@@ -0,0 +1,124 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+use tcod::colors::WHITE;
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::generators::{dig_to_floor, floor_regions, walking_distances};
+use crate::libs::make_map::make_map;
+use crate::libs::themes::choose_theme;
+
+/// join every bit of floor you can't walk to with the rest, smallest first
+pub fn connect_regions(map: &mut Map) {
+    loop {
+        let mut regions = floor_regions(map);
+        if regions.len() < 2 {
+            return;
+        }
+        regions.sort_by_key(|region| region.len());
+        if !dig_to_floor(&regions[0], &HashSet::new(), map) {
+            return;
+        }
+    }
+}
+
+/// everything a level needs to be playable: wall all around, and every floor tile,
+/// the stairs included, within walking distance of where the player starts without
+/// going through lava, chasms or deep water
+pub fn check_map(map: &Map, start: (i32, i32), stairs: (i32, i32)) -> Result<(), String> {
+    let on_border = |x: i32, y: i32| x == 0 || y == 0 || x == MAP_WIDTH - 1 || y == MAP_HEIGHT - 1;
+    let border_floor = (0..MAP_WIDTH)
+        .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)))
+        .any(|(x, y)| on_border(x, y) && map[x as usize][y as usize].passable());
+    if border_floor {
+        return Err("there is floor on the border of the map".into());
+    }
+    if !map[start.0 as usize][start.1 as usize].safe() {
+        return Err(format!("the player starts inside a wall or a hazard at {:?}", start));
+    }
+    let distances = walking_distances(map, start);
+    if distances[stairs.0 as usize][stairs.1 as usize].is_none() {
+        return Err(format!("the stairs at {:?} can't be reached from the start at {:?}", stairs, start));
+    }
+    let unreachable = (0..MAP_WIDTH as usize)
+        .flat_map(|x| (0..MAP_HEIGHT as usize).map(move |y| (x, y)))
+        .filter(|&(x, y)| map[x][y].safe() && distances[x][y].is_none())
+        .count();
+    if unreachable > 0 {
+        return Err(format!("{} floor tiles can't be reached from the start", unreachable));
+    }
+    Ok(())
+}
+
+/// a one tile wide passage, where a monster standing in the way blocks it completely
+pub fn is_corridor(x: i32, y: i32, map: &Map) -> bool {
+    let wall = |x: i32, y: i32| !map[x as usize][y as usize].passable();
+    !wall(x, y) && ((wall(x - 1, y) && wall(x + 1, y)) || (wall(x, y - 1) && wall(x, y + 1)))
+}
+
+/// dig `levels` levels for each of the seeds and check every one of them;
+/// returns what is wrong with each level that has a problem
+pub fn check_maps(seeds: Range<u64>, levels: u32, content: &Content) -> Vec<String> {
+    let mut problems = vec![];
+    for seed in seeds {
+        for level in 1..=levels {
+            let mut objects = Objects::new();
+            objects.insert(Object::new(0, 0, '@', "player", WHITE, true));
+            let mut rng = GameRng::new(seed);
+            let theme = choose_theme(level, &mut rng, content);
+            let (map, _) = make_map(&mut objects, level, theme, &mut rng, content);
+            if let Err(e) = check_level(&map, &objects) {
+                problems.push(format!("seed {}, level {}: {}", seed, level, e));
+            }
+        }
+    }
+    problems
+}
+
+fn check_level(map: &Map, objects: &Objects) -> Result<(), String> {
+    let stairs = objects
+        .iter()
+        .find(|(_, object)| object.name == "stairs down")
+        .map(|(_, object)| object.pos())
+        .ok_or("there are no stairs")?;
+    check_map(map, objects[PLAYER].pos(), stairs)?;
+    let mut taken = HashSet::new();
+    for (_, object) in objects.iter() {
+        let (x, y) = object.pos();
+        if !map[x as usize][y as usize].safe() {
+            return Err(format!("the {} at {:?} is inside a wall or a hazard", object.name, (x, y)));
+        }
+        if object.blocks && !taken.insert((x, y)) {
+            return Err(format!("the {} at {:?} shares its tile with another monster", object.name, (x, y)));
+        }
+        if object.ai.is_some() && is_corridor(x, y, map) {
+            return Err(format!("the {} at {:?} blocks a corridor", object.name, (x, y)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::content::load_content;
+
+    fn assert_playable(seeds: u64) {
+        let content = load_content().expect("the game data loads");
+        let problems = check_maps(0..seeds, CHECK_LEVELS, &content);
+        assert!(problems.is_empty(), "{}", problems.join("\n"));
+    }
+
+    #[test]
+    fn every_level_is_playable() {
+        assert_playable(100);
+    }
+
+    // a few minutes without optimisations: `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    fn thousands_of_seeds_are_playable() {
+        assert_playable(2000);
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashSet;
+use rand::Rng;
+
+use crate::predefs::constants::*;
+use crate::predefs::structs::*;
+use crate::libs::make_map::{random_item, random_monster};
+use crate::libs::generators::dig_to_floor;
+
+/// maybe stamp one of the vaults that can show up on this level into solid rock,
+/// dig a tunnel from each of its ways in to the nearest floor, and fill it up.
+/// returns the vault, if one went in
+pub fn place_vault<'a>(
+    map: &mut Map,
+    objects: &mut Objects,
+    level: u32,
+    theme: &ThemeDef,
+    rng: &mut GameRng,
+    content: &'a Content,
+) -> Option<&'a VaultDef> {
+    let mut candidates: Vec<&VaultDef> = content
+        .vaults
+        .iter()
+        .filter(|vault| level >= vault.depth.min && vault.depth.max.is_none_or(|max| level <= max))
+        .filter(|vault| rng.gen_range(0, vault.rarity) == 0)
+        .collect();
+    // one vault per level at most; if there's no room for the first pick, try the others
+    rng.shuffle(&mut candidates);
+    candidates
+        .into_iter()
+        .find(|vault| try_vault(vault, map, objects, level, theme, rng, content))
+}
+
+/// look for somewhere the vault fits, and put it there if there is
+fn try_vault(vault: &VaultDef, map: &mut Map, objects: &mut Objects, level: u32, theme: &ThemeDef, rng: &mut GameRng, content: &Content) -> bool {
+    let rows = oriented(vault, rng);
+    let (width, height) = (rows[0].len() as i32, rows.len() as i32);
+
+    for _ in 0..VAULT_PLACEMENT_TRIES {
+        // keep a tile clear of the border, so the tunnels have room to get out
+        let x = rng.gen_range(2, MAP_WIDTH - width - 1);
+        let y = rng.gen_range(2, MAP_HEIGHT - height - 1);
+        let footprint: HashSet<(i32, i32)> = (0..width)
+            .flat_map(|dx| (0..height).map(move |dy| (dx, dy)))
+            .filter(|&(dx, dy)| rows[dy as usize][dx as usize] != ' ')
+            .map(|(dx, dy)| (x + dx, y + dy))
+            .collect();
+        if !footprint.iter().all(|&pos| solid_around(map, pos)) {
+            continue;
+        }
+        for (ch, pos) in stamp(&rows, (x, y), map) {
+            let object = match ch {
+                '+' => {
+                    dig_to_floor(&[pos], &footprint, map);
+                    None
+                }
+                'M' => Some(random_monster(level, theme, rng, content).create(pos.0, pos.1)),
+                'I' => Some(random_item(level, theme, rng, content).create(pos.0, pos.1)),
+                '$' => Some(random_item(level + VAULT_TREASURE_DEPTH, theme, rng, content).create(pos.0, pos.1)),
+                _ => vault.legend.get(&ch).and_then(|id| {
+                    let monster = content.monster(id).map(|monster| monster.create(pos.0, pos.1));
+                    monster.or_else(|| content.item(id).map(|item| item.create(pos.0, pos.1)))
+                }),
+            };
+            if let Some(mut object) = object {
+                // items are always seen on the map, like the ones lying around
+                object.always_visible = object.item.is_some();
+                objects.insert(object);
+            }
+        }
+        return true;
+    }
+    false
+}
+
+/// the vault's rows, turned a random number of quarter turns and maybe mirrored
+fn oriented(vault: &VaultDef, rng: &mut GameRng) -> Vec<Vec<char>> {
+    let mut rows: Vec<Vec<char>> = vault.map.iter().map(|row| row.chars().collect()).collect();
+    for _ in 0..rng.gen_range(0, 4) {
+        // a quarter turn clockwise: the first column, read from the bottom up, is the new first row
+        rows = (0..rows[0].len())
+            .map(|x| rows.iter().rev().map(|row| row[x]).collect())
+            .collect();
+    }
+    if rng.gen() {
+        rows.iter_mut().for_each(|row| row.reverse());
+    }
+    rows
+}
+
+/// the tile and everything around it is wall
+fn solid_around(map: &Map, (x, y): (i32, i32)) -> bool {
+    (x - 1..=x + 1).all(|x| (y - 1..=y + 1).all(|y| !map[x as usize][y as usize].passable()))
+}
+
+/// dig the vault into the map, with closed doors for its ways in; returns everything
+/// that isn't plain wall or floor, with where it ended up
+fn stamp(rows: &[Vec<char>], (x, y): (i32, i32), map: &mut Map) -> Vec<(char, (i32, i32))> {
+    let mut markers = vec![];
+    for (dy, row) in rows.iter().enumerate() {
+        for (dx, &ch) in row.iter().enumerate() {
+            let pos = (x + dx as i32, y + dy as i32);
+            map[pos.0 as usize][pos.1 as usize] = match ch {
+                ' ' => continue,
+                '#' => Tile::wall(),
+                '+' => Tile::door(false),
+                _ => Tile::empty(),
+            };
+            if ch != '#' && ch != '.' {
+                markers.push((ch, pos));
+            }
+        }
+    }
+    markers
+}
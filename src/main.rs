@@ -1,5 +1,5 @@
-use tcod::console::*;
-use tcod::map::Map as FovMap;
+use std::env;
+use std::io;
 
 mod predefs;
 use predefs::constants::*;
@@ -7,28 +7,90 @@ use predefs::structs::*;
 
 mod libs;
 use libs::menu::{main_menu};
+use libs::replay::*;
+use libs::content::load_content;
+use libs::validate::check_maps;
+use libs::save::import_legacy_save;
+use libs::keymap::load_keymap;
+use libs::backend::Backend;
+use libs::backend::window::WindowBackend;
+use libs::backend::snapshot::SnapshotBackend;
 
 fn main() {
-    tcod::system::set_fps(LIMIT_FPS);
-
-    let root = Root::initializer()
-        .font("consolas.png", FontLayout::Tcod)
-        .font_type(FontType::Greyscale)
-        .size(SCREEN_WIDTH, SCREEN_HEIGHT)
-        .title("ARROBA")
-        .init();
-
-    let mut tcod = Tcod {
-        root,
-        con: Offscreen::new(MAP_WIDTH, MAP_HEIGHT),
-        panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT),
-        fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
-        key: Default::default(),
-        mouse: Default::default(),
+    let args: Vec<String> = env::args().collect();
+    let content = load_content().unwrap_or_else(|e| panic!("Could not load the game data: {}", e));
+    // pick one of the presets of data/keymap.json: `a-robber --keys vi`
+    let keymap = load_keymap(arg_value(&args, "--keys"))
+        .unwrap_or_else(|e| panic!("Could not load the key bindings: {}", e));
+    // an explicit seed replays the same dungeon: `a-robber --seed 1234`
+    let seed = arg_value(&args, "--seed")
+        .map(|seed| seed.parse().expect("The seed must be a positive number"));
+    // `--save-format binary` or `compressed` writes smaller saves than the default JSON
+    let save_format = match arg_value(&args, "--save-format") {
+        None | Some("json") => SaveFormat::Json,
+        Some("binary") => SaveFormat::Binary { compressed: false },
+        Some("compressed") => SaveFormat::Binary { compressed: true },
+        Some(other) => panic!("Unknown save format \"{}\", use json, binary or compressed", other),
     };
-    
 
-    main_menu(&mut tcod);
+    // dig lots of levels and check every one is playable: `a-robber --check-maps 1000`
+    if let Some(seeds) = arg_value(&args, "--check-maps") {
+        let seeds = seeds.parse().expect("The number of seeds must be a positive number");
+        let problems = check_maps(0..seeds, CHECK_LEVELS, &content);
+        for problem in &problems {
+            println!("{}", problem);
+        }
+        println!("checked {} levels, {} with problems", seeds * CHECK_LEVELS as u64, problems.len());
+        std::process::exit(if problems.is_empty() { 0 } else { 1 });
+    }
+
+    // watch a recorded run again: `a-robber --replay replays/1234-1616868300.json [--speed 50] [--headless | --snapshot]`
+    if let Some(path) = arg_value(&args, "--replay") {
+        let replay = load_replay(path).expect("Could not read the replay file");
+        if args.iter().any(|arg| arg == "--headless") {
+            print_summary(&replay_headless(&replay, &content));
+        } else if args.iter().any(|arg| arg == "--snapshot") {
+            // the last screen of the run, as text
+            let snapshot = SnapshotBackend::new(Box::new(io::stdout()), vec![]);
+            print_screen(&mut Ui::new(Box::new(snapshot), keymap), &replay_headless(&replay, &content));
+        } else {
+            let speed = arg_value(&args, "--speed")
+                .map_or(DEFAULT_REPLAY_DELAY, |speed| speed.parse().expect("The speed must be in milliseconds"));
+            replay_game(&mut init_ui(&args, keymap), &replay, &content, speed);
+        }
+        return;
+    }
+
+    import_legacy_save();
+    main_menu(&mut init_ui(&args, keymap), &content, seed, save_format);
 }
 
+/// the value following a command line flag
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .skip_while(|arg| *arg != flag)
+        .nth(1)
+        .map(|value| value.as_str())
+}
 
+/// a game window, or the terminal itself with `--terminal`
+fn init_ui(args: &[String], keymap: Keymap) -> Ui {
+    let backend: Box<dyn Backend> = if args.iter().any(|arg| arg == "--terminal") {
+        terminal_backend()
+    } else {
+        Box::new(WindowBackend::new())
+    };
+    Ui::new(backend, keymap)
+}
+
+#[cfg(unix)]
+fn terminal_backend() -> Box<dyn Backend> {
+    let terminal = libs::backend::terminal::TerminalBackend::new()
+        .unwrap_or_else(|e| panic!("Could not play in the terminal: {}", e));
+    Box::new(terminal)
+}
+
+#[cfg(not(unix))]
+fn terminal_backend() -> Box<dyn Backend> {
+    panic!("Playing in the terminal is only supported on unix systems")
+}
@@ -1,33 +1,101 @@
 use tcod::colors::*;
 use tcod::map::FovAlgorithm;
 
-pub const PLAYER: usize = 0;
+use crate::predefs::structs::EntityId;
+
+// the player is always the first object created, and never removed
+pub const PLAYER: EntityId = EntityId { index: 0, generation: 0 };
 
 pub const SCREEN_WIDTH: i32 = 80;
 pub const SCREEN_HEIGHT: i32 = 50;
 
-pub const MAP_WIDTH: i32 = 80;
-pub const MAP_HEIGHT: i32 = 43;
+// levels are bigger than the screen; the part above the panel shows VIEW_WIDTH by
+// VIEW_HEIGHT tiles of it, following the player around
+pub const MAP_WIDTH: i32 = 120;
+pub const MAP_HEIGHT: i32 = 70;
+pub const VIEW_WIDTH: i32 = 80;
+pub const VIEW_HEIGHT: i32 = 43;
+
+// how each kind of level is dug is up to its theme, in data/themes.json.
+// a level failing its checks is dug again, up to MAP_TRIES times with the generators of
+// its theme, and then with plain rooms of these sizes (and no vault), which always pass
+pub const MAP_TRIES: u32 = 10;
+pub const FALLBACK_MAX_ROOMS: i32 = 70;
+pub const FALLBACK_ROOM_MIN_SIZE: i32 = 6;
+pub const FALLBACK_ROOM_MAX_SIZE: i32 = 10;
+// caves with too little floor for their theme are grown again this many times at most
+pub const CAVE_TRIES: u32 = 5;
+// caves are shared out in squares of this size when placing monsters and items, and
+// a square needs CAVE_MIN_REGION floor tiles to get a share of its own
+pub const CAVE_REGION_SIZE: i32 = 11;
+pub const CAVE_MIN_REGION: usize = 36;
+
+// vaults look for a spot of solid rock this many times before giving up on the level
+pub const VAULT_PLACEMENT_TRIES: u32 = 100;
+// treasure is picked from the items of a level this much deeper
+pub const VAULT_TREASURE_DEPTH: u32 = 3;
+// the characters every vault map understands, on top of its own legend
+pub const VAULT_TILES: &str = "#.+MI$ ";
+// chance for each way into a room to get a door, and for a door to be locked
+pub const DOOR_CHANCE: f32 = 0.6;
+pub const LOCKED_DOOR_CHANCE: f32 = 0.1;
+// chance for each push at a locked door to force it open
+pub const FORCE_DOOR_CHANCE: f32 = 0.3;
+
+// pools of water, lava and chasms: each one grows to between HAZARD_MIN_SIZE and
+// HAZARD_MAX_SIZE tiles, and is left out if it would cut off part of the level
+pub const HAZARD_MIN_SIZE: usize = 6;
+pub const HAZARD_MAX_SIZE: usize = 30;
+// chance for each step out of shallow water to get stuck instead
+pub const WADING_STUCK_CHANCE: f32 = 0.5;
+// chance for each stroke in deep water to lose hold of something from the inventory
+pub const LOSE_GRIP_CHANCE: f32 = 0.2;
+pub const LAVA_DAMAGE: i32 = 10;
+pub const CHASM_DAMAGE: i32 = 8;
 
-pub const ROOM_MAX_SIZE: i32 = 10;
-pub const ROOM_MIN_SIZE: i32 = 6;
-pub const MAX_ROOMS: i32 = 30;
+// hidden traps: the chance to notice each one next to the player every turn, and
+// to find each one within SEARCH_RADIUS when searching on purpose
+pub const TRAP_SPOT_CHANCE: f32 = 0.1;
+pub const SEARCH_RADIUS: i32 = 2;
+pub const SEARCH_CHANCE: f32 = 0.6;
+// chance to take a known trap apart; a failed try sets it off some of the time
+pub const DISARM_CHANCE: f32 = 0.6;
+pub const DISARM_SPRING_CHANCE: f32 = 0.5;
+// secret doors and passages are harder to find than traps, searching or not
+pub const SECRET_SPOT_CHANCE: f32 = 0.05;
+pub const SECRET_SEARCH_CHANCE: f32 = 0.3;
+
+// how deep `--check-maps` goes for every seed
+pub const CHECK_LEVELS: u32 = 10;
 
 pub const INVENTORY_WIDTH: i32 = 50;
 
-pub const HEAL_AMOUNT: i32 = 40;
-pub const LIGHTNING_DAMAGE: i32 = 40;
-pub const LIGHTNING_RANGE: i32 = 5;
-pub const CONFUSE_RANGE: i32 = 8;
-pub const CONFUSE_NUM_TURNS: i32 = 10;
-pub const FIREBALL_RADIUS: i32 = 3;
-pub const FIREBALL_DAMAGE: i32 = 25;
+// bump when the saved data changes, and add a migration from the previous format. saves
+// also record the version in Cargo.toml, so a release with a new format needs a new version
+pub const SAVE_FORMAT_VERSION: u32 = 9;
+pub const SAVE_SLOTS: usize = 5;
+pub const SLOT_SCREEN_WIDTH: i32 = 64;
+// the game also saves itself on every new dungeon level
+pub const AUTOSAVE_TURNS: u32 = 100;
+
+// game data, relative to the working directory like the font
+pub const MONSTERS_FILE: &str = "data/monsters.json";
+pub const ITEMS_FILE: &str = "data/items.json";
+pub const VAULTS_FILE: &str = "data/vaults.json";
+pub const TRAPS_FILE: &str = "data/traps.json";
+pub const THEMES_FILE: &str = "data/themes.json";
+pub const KEYMAP_FILE: &str = "data/keymap.json";
+pub const FONT_FILE: &str = "consolas.png";
 
 pub const COLOR_DARK_WALL: Color = Color { r: 0, g: 0, b: 100 };
 pub const COLOR_DARK_GROUND: Color = Color { r: 50, g: 50, b: 150,};
 
 pub const LIMIT_FPS: i32 = 20; // 20 frames-per-second maximum
 
+// milliseconds between two replayed commands
+pub const DEFAULT_REPLAY_DELAY: u64 = 100;
+pub const MAX_REPLAY_DELAY: u64 = 2000;
+
 pub const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic; // default FOV algorithm
 pub const FOV_LIGHT_WALLS: bool = true; // light walls or not
 pub const TORCH_RADIUS: i32 = 10;
@@ -42,6 +110,12 @@ pub const COLOR_LIGHT_GROUND: Color = Color {
     g: 180,
     b: 50,
 };
+pub const COLOR_DOOR: Color = Color { r: 100, g: 60, b: 20 };
+// hazards are drawn like this in sight, and at half the brightness out of it
+pub const COLOR_SHALLOW_WATER: Color = Color { r: 60, g: 110, b: 200 };
+pub const COLOR_DEEP_WATER: Color = Color { r: 20, g: 40, b: 150 };
+pub const COLOR_LAVA: Color = Color { r: 210, g: 70, b: 0 };
+pub const COLOR_CHASM: Color = Color { r: 25, g: 15, b: 35 };
 
 // sizes and coordinates relevant for the GUI
 pub const BAR_WIDTH: i32 = 20;
@@ -56,4 +130,5 @@ pub const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
 pub const LEVEL_UP_BASE: i32 = 200;
 pub const LEVEL_UP_FACTOR: i32 = 150;
 pub const LEVEL_SCREEN_WIDTH: i32 = 40;
-pub const CHARACTER_SCREEN_WIDTH: i32 = 30;
\ No newline at end of file
+pub const CHARACTER_SCREEN_WIDTH: i32 = 30;
+pub const SCREENSHOT_WIDTH: i32 = 70;
\ No newline at end of file
@@ -1,19 +1,315 @@
 
+use std::cmp;
+use std::collections::BTreeMap;
+use std::ops::{Index, IndexMut};
+
 use serde::{Deserialize, Serialize};
-use tcod::console::*;
 use tcod::colors::*;
 use tcod::map::Map as FovMap;
-use tcod::input::{Key, Mouse};
+use tcod::input::KeyCode;
 use crate::libs::handle_keys::*;
+use crate::libs::save::{serialize_map, deserialize_map};
+use crate::libs::backend::Backend;
+
+
+/// what the player sees and plays with: the screen is drawn on canvases, and
+/// the backend (a ui window or a terminal) shows it and reads the input
+pub struct Ui {
+    pub backend: Box<dyn Backend>,
+    pub root: Canvas,
+    pub con: Canvas,
+    pub panel: Canvas,
+    pub key: KeyPress,  
+    pub mouse: MouseState,
+    pub keymap: Keymap,
+}
+
+/// a grid of characters with their colours, to be drawn on and shown by a backend
+#[derive(Clone, Debug, PartialEq)]
+pub struct Canvas {
+    pub width: i32,
+    pub height: i32,
+    pub cells: Vec<Cell>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cell {
+    pub ch: char,
+    pub fg: Color,
+    pub bg: Color,
+}
+
+/// a key press, whatever the backend
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyPress {
+    pub code: KeyCode,
+    /// the character typed, for `KeyCode::Text` and `KeyCode::Char`
+    pub printable: char,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl Default for KeyPress {
+    fn default() -> Self {
+        KeyPress {
+            code: KeyCode::NoKey,
+            printable: '\0',
+            ctrl: false,
+            alt: false,
+        }
+    }
+}
+
+/// where the mouse is, in console cells, and whether it was just clicked
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MouseState {
+    pub x: i32,
+    pub y: i32,
+    pub left_click: bool,
+    pub right_click: bool,
+}
 
+/// something the player did, as read from a backend
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    Key(KeyPress),
+    Mouse(MouseState),
+}
 
-pub struct Tcod {
-    pub root: Root,
-    pub con: Offscreen,
-    pub panel: Offscreen,
+/// everything needed to simulate a run, without any window or console attached
+pub struct GameState {
+    pub game: Game,
+    pub objects: Objects,
     pub fov: FovMap,
-    pub key: Key,  
-    pub mouse: Mouse,
+    pub pending: Option<Prompt>,
+    pub content: Content,
+    /// the save slot of the run, if it is kept in one; it is emptied when the player dies
+    pub save_slot: Option<usize>,
+}
+
+/// game data loaded at startup from the files in `data/`
+#[derive(Clone, Debug)]
+pub struct Content {
+    pub monsters: Vec<MonsterDef>,
+    pub items: Vec<ItemDef>,
+    /// ids of the items every new character starts with, equipped if possible
+    pub starting_inventory: Vec<String>,
+    pub vaults: Vec<VaultDef>,
+    pub traps: Vec<TrapDef>,
+    pub themes: Vec<ThemeDef>,
+}
+
+impl Content {
+    pub fn monster(&self, id: &str) -> Option<&MonsterDef> {
+        self.monsters.iter().find(|monster| monster.id == id)
+    }
+
+    pub fn item(&self, id: &str) -> Option<&ItemDef> {
+        self.items.iter().find(|item| item.id == id)
+    }
+
+    pub fn theme(&self, id: &str) -> Option<&ThemeDef> {
+        self.themes.iter().find(|theme| theme.id == id)
+    }
+
+    /// the colours of a level with this theme; levels without one keep the old blue and yellow
+    pub fn palette(&self, theme: Option<&str>) -> Palette {
+        theme
+            .and_then(|id| self.theme(id))
+            .map_or_else(Palette::default, |theme| theme.palette)
+    }
+}
+
+/// a stretch of the dungeon with its own look, layout and inhabitants, as described in
+/// `data/themes.json`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ThemeDef {
+    pub id: String,
+    pub name: String,
+    /// relative chance for a level to have this theme, by dungeon level
+    pub spawn: Vec<Transition>,
+    pub palette: Palette,
+    /// the ways its levels are dug, one picked at random by weight
+    pub generators: Vec<GeneratorDef>,
+    /// pools of its own, instead of the usual ones for the depth
+    #[serde(default)]
+    pub hazards: Option<HazardsDef>,
+    /// spawn tables, by id, for the monsters and items that show up more or less
+    /// often here than elsewhere; an empty one keeps them away
+    #[serde(default)]
+    pub monsters: BTreeMap<String, Vec<Transition>>,
+    #[serde(default)]
+    pub items: BTreeMap<String, Vec<Transition>>,
+    /// said when the player first gets to one of its levels
+    pub arrival: String,
+}
+
+impl ThemeDef {
+    /// how likely a monster is to show up on this theme's levels
+    pub fn monster_spawn<'a>(&'a self, monster: &'a MonsterDef) -> &'a [Transition] {
+        self.monsters.get(&monster.id).unwrap_or(&monster.spawn)
+    }
+
+    pub fn item_spawn<'a>(&'a self, item: &'a ItemDef) -> &'a [Transition] {
+        self.items.get(&item.id).unwrap_or(&item.spawn)
+    }
+}
+
+/// the backgrounds of walls and floor (doors included), in and out of sight
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Palette {
+    pub dark_wall: Color,
+    pub light_wall: Color,
+    pub dark_ground: Color,
+    pub light_ground: Color,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        use crate::predefs::constants::*;
+        Palette {
+            dark_wall: COLOR_DARK_WALL,
+            light_wall: COLOR_LIGHT_WALL,
+            dark_ground: COLOR_DARK_GROUND,
+            light_ground: COLOR_LIGHT_GROUND,
+        }
+    }
+}
+
+/// one of the map generators, with its settings and how often it is picked
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct GeneratorDef {
+    pub weight: u32,
+    #[serde(flatten)]
+    pub kind: GeneratorKind,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GeneratorKind {
+    /// up to `max_rooms` rooms scattered at random, each joined to the one before
+    Rooms { max_rooms: i32, room_min_size: i32, room_max_size: i32 },
+    /// the map cut in two again and again, until the pieces are at most `max_leaf`
+    /// big; each piece gets a room, and `loop_chance` of an extra tunnel
+    Bsp { min_leaf: i32, max_leaf: i32, min_room: i32, loop_chance: f32 },
+    /// `wall_chance` of walls at random, smoothed out; caves with less floor than
+    /// `min_floor` tiles are dug again
+    Caves { wall_chance: f32, smoothing_passes: u32, min_floor: usize },
+}
+
+/// how many pools a level gets at most, and how often each kind is picked
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct HazardsDef {
+    pub max_pools: u32,
+    pub water: u32,
+    pub lava: u32,
+    pub chasm: u32,
+}
+
+/// a kind of trap, as described in `data/traps.json`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrapDef {
+    pub id: String,
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub effect: TrapEffect,
+    /// relative chance to show up, by dungeon level
+    pub spawn: Vec<Transition>,
+}
+
+impl TrapDef {
+    /// a trap nobody knows about yet
+    pub fn create(&self, x: i32, y: i32) -> Object {
+        let mut trap = Object::new(x, y, self.glyph, &self.name, self.color, false);
+        trap.trap = Some(Trap {
+            effect: self.effect,
+            hidden: true,
+        });
+        trap.always_visible = true;
+        trap
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Trap {
+    pub effect: TrapEffect,
+    /// not drawn until the player finds it, by searching or by setting it off
+    pub hidden: bool,
+}
+
+/// what happens to whatever sets a trap off
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TrapEffect {
+    Dart { damage: i32 },
+    /// somewhere else on the level, at random
+    Teleport,
+    Confusion { turns: i32 },
+    /// wakes up the monsters within `radius`, and sends them to see what is going on
+    Alarm { radius: i32 },
+    /// opens a chasm to the level below
+    Trapdoor,
+}
+
+/// a hand-drawn room from `data/vaults.json`, stamped into levels as a whole,
+/// turned and mirrored at random
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VaultDef {
+    pub id: String,
+    pub depth: Depth,
+    /// shows up on about one in `rarity` of the levels it can be on
+    pub rarity: u32,
+    /// more map characters, standing for a particular monster or item (by id)
+    #[serde(default)]
+    pub legend: BTreeMap<char, String>,
+    /// one string per row: `#` wall, `.` floor, `+` way in, `M` a random monster,
+    /// `I` a random item, `$` treasure, and a space for whatever was there before
+    pub map: Vec<String>,
+}
+
+/// the dungeon levels something can be found on; no `max` means all the way down
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Depth {
+    pub min: u32,
+    #[serde(default)]
+    pub max: Option<u32>,
+}
+
+/// a kind of monster, as described in `data/monsters.json`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MonsterDef {
+    pub id: String,
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub hp: i32,
+    pub defense: i32,
+    pub power: i32,
+    pub xp: i32,
+    pub ai: Ai,
+    /// hazards it crosses unharmed, like deep water for a swimmer
+    #[serde(default)]
+    pub immune: Vec<Hazard>,
+    /// relative chance to show up, by dungeon level
+    pub spawn: Vec<Transition>,
+}
+
+impl MonsterDef {
+    pub fn create(&self, x: i32, y: i32) -> Object {
+        let mut monster = Object::new(x, y, self.glyph, &self.name, self.color, true);
+        monster.fighter = Some(Fighter {
+            base_max_hp: self.hp,
+            hp: self.hp,
+            base_defense: self.defense,
+            base_power: self.power,
+            xp: self.xp,
+            on_death: DeathCallback::Monster,
+        });
+        monster.ai = Some(self.ai.clone());
+        monster.immune = self.immune.clone();
+        monster.alive = true;
+        monster
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,17 +327,19 @@ pub struct Object{
     pub always_visible: bool,
     pub level: i32,
     pub equipment: Option<Equipment>,
+    pub immune: Vec<Hazard>,
+    pub trap: Option<Trap>,
 }
 
 impl Object{
     pub fn new (x: i32, y: i32, char: char, name: &str, color: Color, blocks: bool) -> Self{
         Object {
-            x: x,
-            y: y,
-            char: char,
-            color: color,
+            x,
+            y,
+            char,
+            color,
             name: name.into(),
-            blocks: blocks,
+            blocks,
             alive: false,
             fighter: None,
             ai: None,
@@ -49,6 +347,8 @@ impl Object{
             level:1,
             always_visible: false,
             equipment: None,
+            immune: vec![],
+            trap: None,
         }
     }
 
@@ -71,6 +371,14 @@ impl Object{
         (((x - self.x).pow(2) + (y - self.y).pow(2)) as f32).sqrt()
     }
 
+    /// the hazard on a tile, unless this object is immune to it
+    pub fn hazard_in(&self, tile: &Tile) -> Option<Hazard> {
+        match tile.kind {
+            TileKind::Hazard(hazard) if !self.immune.contains(&hazard) => Some(hazard),
+            _ => None,
+        }
+    }
+
     pub fn take_damage(&mut self, damage: i32, game: &mut Game) -> Option<i32> {
         // apply damage if possible
         if let Some(fighter) = self.fighter.as_mut() {
@@ -176,7 +484,7 @@ impl Object{
         if self.name == "player" {
             game.inventory
                 .iter()
-                .filter(|item| item.equipment.map_or(false, |e| e.equipped))
+                .filter(|item| item.equipment.is_some_and(|e| e.equipped))
                 .map(|item| item.equipment.unwrap())
                 .collect()
         } else {
@@ -210,45 +518,408 @@ impl Object{
     }
     
     /// set the color and then draw the character that represents this object at its position
-    pub fn draw(&self, con: &mut dyn Console) {
-        con.set_default_foreground(self.color);
-        con.put_char(self.x, self.y, self.char, BackgroundFlag::None);
+    /// draw it where it is on screen, with `camera` the top left corner of the view
+    pub fn draw(&self, con: &mut Canvas, (camera_x, camera_y): (i32, i32)) {
+        con.put_char(self.x - camera_x, self.y - camera_y, self.char, self.color);
+    }
+}
+
+/// a handle to an object on the map. It stays valid for as long as the object
+/// exists, and never points to another object once that one is removed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EntityId {
+    pub index: usize,
+    pub generation: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    generation: u32,
+    object: Option<Object>,
+}
+
+/// all the objects on the map, addressed by `EntityId`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Objects {
+    entries: Vec<Entry>,
+    // indices of removed objects, ready to be reused
+    free: Vec<usize>,
+}
+
+impl Objects {
+    pub fn new() -> Self {
+        Objects {
+            entries: vec![],
+            free: vec![],
+        }
+    }
+
+    pub fn insert(&mut self, object: Object) -> EntityId {
+        match self.free.pop() {
+            Some(index) => {
+                let entry = &mut self.entries[index];
+                entry.object = Some(object);
+                EntityId { index, generation: entry.generation }
+            }
+            None => {
+                self.entries.push(Entry { generation: 0, object: Some(object) });
+                EntityId { index: self.entries.len() - 1, generation: 0 }
+            }
+        }
+    }
+
+    /// take the object out; its id (and any copy of it) becomes stale
+    pub fn remove(&mut self, id: EntityId) -> Option<Object> {
+        if !self.contains(id) {
+            return None;
+        }
+        let entry = &mut self.entries[id.index];
+        entry.generation += 1;
+        self.free.push(id.index);
+        entry.object.take()
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&Object> {
+        self.entries
+            .get(id.index)
+            .filter(|entry| entry.generation == id.generation)
+            .and_then(|entry| entry.object.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut Object> {
+        self.entries
+            .get_mut(id.index)
+            .filter(|entry| entry.generation == id.generation)
+            .and_then(|entry| entry.object.as_mut())
+    }
+
+    /// mutable access to two different objects at once
+    pub fn pair_mut(&mut self, first: EntityId, second: EntityId) -> (&mut Object, &mut Object) {
+        assert!(first.index != second.index);
+        let split_at_index = cmp::max(first.index, second.index);
+        let (first_slice, second_slice) = self.entries.split_at_mut(split_at_index);
+        let (first_entry, second_entry) = if first.index < second.index {
+            (&mut first_slice[first.index], &mut second_slice[0])
+        } else {
+            (&mut second_slice[0], &mut first_slice[second.index])
+        };
+        assert!(first_entry.generation == first.generation && second_entry.generation == second.generation);
+        (
+            first_entry.object.as_mut().expect("no object for this id"),
+            second_entry.object.as_mut().expect("no object for this id"),
+        )
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &Object)> {
+        self.entries.iter().enumerate().filter_map(|(index, entry)| {
+            let id = EntityId { index, generation: entry.generation };
+            entry.object.as_ref().map(|object| (id, object))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &mut Object)> {
+        self.entries.iter_mut().enumerate().filter_map(|(index, entry)| {
+            let id = EntityId { index, generation: entry.generation };
+            entry.object.as_mut().map(|object| (id, object))
+        })
+    }
+
+    /// take out every object except one (the player), to put them away with their level
+    pub fn remove_all_except(&mut self, keep: EntityId) -> Vec<Object> {
+        self.ids()
+            .into_iter()
+            .filter(|&id| id != keep)
+            .filter_map(|id| self.remove(id))
+            .collect()
+    }
+
+    /// the ids of every object, safe to walk while objects are added or removed
+    pub fn ids(&self) -> Vec<EntityId> {
+        self.iter().map(|(id, _)| id).collect()
+    }
+
+    /// remove every object for which `keep` returns false
+    pub fn retain<F: FnMut(EntityId, &Object) -> bool>(&mut self, mut keep: F) {
+        let removed: Vec<_> = self
+            .iter()
+            .filter(|&(id, object)| !keep(id, object))
+            .map(|(id, _)| id)
+            .collect();
+        for id in removed {
+            self.remove(id);
+        }
+    }
+}
+
+impl Index<EntityId> for Objects {
+    type Output = Object;
+
+    fn index(&self, id: EntityId) -> &Object {
+        self.get(id).expect("no object for this id")
+    }
+}
+
+impl IndexMut<EntityId> for Objects {
+    fn index_mut(&mut self, id: EntityId) -> &mut Object {
+        self.get_mut(id).expect("no object for this id")
     }
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Tile {
-    pub blocked: bool,
+    pub kind: TileKind,
     pub explored: bool,
-    pub block_sight: bool,
+}
+
+/// what a tile is made of
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TileKind {
+    Floor,
+    Wall,
+    /// a locked door stays shut until the player forces it open
+    Door { open: bool, locked: bool },
+    Hazard(Hazard),
+    /// looks and acts just like a wall until it is found; then it turns out to be a
+    /// door, or a bit of passage
+    Secret { door: bool },
+}
+
+/// terrain that is dangerous to go into, for whatever isn't immune to it
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hazard {
+    /// slows down whatever wades through it
+    ShallowWater,
+    /// monsters that can't swim keep out; the player swims, but may lose things in it
+    DeepWater,
+    /// burns whatever stands in it
+    Lava,
+    /// whatever goes in falls to the level below
+    Chasm,
+}
+
+impl Hazard {
+    /// worse than just slowing you down
+    pub fn harmful(self) -> bool {
+        self != Hazard::ShallowWater
+    }
 }
 
 impl Tile {
     pub fn empty() -> Self {
         Tile {
-            blocked: false,
-            explored:false,
-            block_sight: false,
+            kind: TileKind::Floor,
+            explored: false,
         }
     }
 
     pub fn wall() -> Self {
         Tile {
-            blocked: true,
-            explored:false,
-            block_sight: true,
+            kind: TileKind::Wall,
+            explored: false,
+        }
+    }
+
+    /// a closed door
+    pub fn door(locked: bool) -> Self {
+        Tile {
+            kind: TileKind::Door { open: false, locked },
+            explored: false,
+        }
+    }
+
+    pub fn hazard(hazard: Hazard) -> Self {
+        Tile {
+            kind: TileKind::Hazard(hazard),
+            explored: false,
+        }
+    }
+
+    /// nothing can stand here right now
+    pub fn blocked(&self) -> bool {
+        match self.kind {
+            TileKind::Floor | TileKind::Hazard(_) => false,
+            TileKind::Wall | TileKind::Secret { .. } => true,
+            TileKind::Door { open, .. } => !open,
+        }
+    }
+
+    pub fn block_sight(&self) -> bool {
+        self.blocked()
+    }
+
+    /// there is a way through, maybe after opening a door. secrets don't count, since
+    /// nobody knows about them
+    pub fn passable(&self) -> bool {
+        !matches!(self.kind, TileKind::Wall | TileKind::Secret { .. })
+    }
+
+    /// passable, and nothing worse than wet feet on the way
+    pub fn safe(&self) -> bool {
+        match self.kind {
+            TileKind::Wall | TileKind::Secret { .. } => false,
+            TileKind::Hazard(hazard) => !hazard.harmful(),
+            _ => true,
         }
     }
 }
 
 pub type Map = Vec<Vec<Tile>>;
 
+/// a bunch of floor tiles that get their share of monsters and items together
+pub type Region = Vec<(i32, i32)>;
+
 #[derive(Serialize, Deserialize)]
 pub struct Game{
+    #[serde(serialize_with = "serialize_map", deserialize_with = "deserialize_map")]
     pub map: Map,
     pub messages: Messages,
     pub inventory: Vec<Object>,
     pub dungeon_level: u32,
+    /// every level visited so far, except the one the player is on
+    pub levels: Vec<Level>,
+    /// the id of the current level's theme; levels from older saves have none
+    pub theme: Option<String>,
+    /// turns taken by the player so far
+    pub turns: u32,
+    /// turns the player has left stumbling around at random
+    pub confused_turns: i32,
+    /// unknown for runs upgraded from 0.1.0 saves, which can't be replayed
+    pub seed: Option<u64>,
+    pub rng: GameRng,
+    /// every command given since the start of the run, to replay it later
+    pub commands: Vec<PlayerCommand>,
+}
+
+/// a level the player has left, just as it was, to go back to later
+#[derive(Serialize, Deserialize)]
+pub struct Level {
+    pub dungeon_level: u32,
+    pub theme: Option<String>,
+    #[serde(serialize_with = "serialize_map", deserialize_with = "deserialize_map")]
+    pub map: Map,
+    /// everything on it but the player
+    pub objects: Vec<Object>,
+}
+
+/// written in front of every save, so older saves can be recognised and upgraded
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveHeader {
+    pub format_version: u32,
+    /// version of the game that wrote the save
+    pub game_version: String,
+    pub summary: SaveSummary,
+}
+
+/// what a save slot shows before loading it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveSummary {
+    pub character_level: i32,
+    pub dungeon_level: u32,
+    pub turns: u32,
+    /// seconds since the unix epoch, 0 if unknown
+    pub saved_at: u64,
+}
+
+/// how games are written to disk; loading works with any of them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SaveFormat {
+    /// readable, and easy to fix by hand
+    Json,
+    /// MessagePack with a bit-packed map, optionally deflated
+    Binary { compressed: bool },
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    NotFound,
+    /// written by a newer build, with a format this one doesn't know
+    Incompatible { format_version: u32, game_version: String },
+    Corrupt(String),
+    /// the character died; dead runs are gone for good
+    Dead,
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SaveError::NotFound => write!(f, "No saved game to load."),
+            SaveError::Incompatible { format_version, game_version } => write!(
+                f,
+                "This save comes from version {} (save format {}), which is newer than this game.",
+                game_version, format_version
+            ),
+            SaveError::Corrupt(reason) => write!(f, "The saved game is damaged: {}", reason),
+            SaveError::Dead => write!(f, "This character is dead, there is nothing to continue."),
+            SaveError::Io(e) => write!(f, "Could not access the saved game: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::NotFound => SaveError::NotFound,
+            _ => SaveError::Io(e),
+        }
+    }
+}
+
+/// everything needed to reproduce a run: replaying the commands on a new game
+/// with the same seed leads to exactly the same state
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    pub game_version: String,
+    pub seed: u64,
+    pub commands: Vec<PlayerCommand>,
+}
+
+/// the single source of randomness of a run (xorshift64*), so the same seed
+/// always yields the same dungeon, loot and monster behaviour
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameRng {
+    state: u64,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        // scramble the seed (splitmix64), so nearby seeds give unrelated runs
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        // xorshift gets stuck on zero
+        GameRng { state: if z == 0 { 1 } else { z } }
+    }
+}
+
+impl rand::Rng for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+/// a freshly dug level: its tiles, the regions to fill with monsters and items,
+/// where the player starts and where the stairs go
+pub struct Layout {
+    pub map: Map,
+    pub regions: Vec<Region>,
+    pub start: (i32, i32),
+    pub stairs: (i32, i32),
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
@@ -273,6 +944,13 @@ impl Rect {
         let center_y = (self.y1 + self.y2) / 2;
         (center_x, center_y)
     }
+
+    /// the tiles inside the walls
+    pub fn floor(&self) -> Region {
+        ((self.x1 + 1)..self.x2)
+            .flat_map(|x| ((self.y1 + 1)..self.y2).map(move |y| (x, y)))
+            .collect()
+    }
     
     pub fn intersects_with(&self, other: &Rect) -> bool {
         // returns true if this rectangle intersects with another one
@@ -283,13 +961,121 @@ impl Rect {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq,Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum PlayerAction {
     TookTurn,
     DidntTakeTurn,
+}
+
+/// a single decision of the player, as understood by the game core
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PlayerCommand {
+    /// move in a direction, attacking whatever blocks the way
+    Move { dx: i32, dy: i32 },
+    Wait,
+    PickUp,
+    UseItem(usize),
+    DropItem(usize),
+    Descend,
+    Ascend,
+    /// shut the open doors next to the player
+    CloseDoor,
+    /// look around for hidden traps
+    Search,
+    /// take apart a known trap next to the player
+    Disarm,
+    /// answer to `Prompt::LevelUp`
+    LevelUp(Stat),
+    /// answer to `Prompt::Target`, `None` cancels
+    Target(Option<(i32, i32)>),
+}
+
+/// everything a key can be bound to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyCommand {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    MoveUpLeft,
+    MoveUpRight,
+    MoveDownLeft,
+    MoveDownRight,
+    Wait,
+    PickUp,
+    Inventory,
+    Drop,
+    Descend,
+    Ascend,
+    CloseDoor,
+    Search,
+    Disarm,
+    Character,
+    Screenshot,
+    ExportMap,
+    Fullscreen,
     Exit,
 }
 
+/// a key, with the modifiers that must be held with it
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyBinding {
+    pub key: BoundKey,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BoundKey {
+    /// a special key, like the arrows or the numpad
+    Code(KeyCode),
+    /// whatever key types this character, on any keyboard layout
+    Text(char),
+}
+
+/// which command each key triggers, from `data/keymap.json`
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    pub bindings: Vec<(KeyBinding, KeyCommand)>,
+}
+
+/// what the front end decided to do with a key press
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyAction {
+    Command(PlayerCommand),
+    Ignored,
+    Exit,
+}
+
+/// a question the game core needs the front end to answer with a `PlayerCommand`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Prompt {
+    LevelUp,
+    Target {
+        inventory_id: usize,
+        max_range: Option<f32>,
+        // only accept tiles with a monster on them
+        monster: bool,
+    },
+}
+
+/// notable things that happened while stepping the game
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameEvent {
+    TurnTaken,
+    LevelChanged(u32),
+    PlayerDied,
+    Prompt(Prompt),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Stat {
+    Constitution,
+    Strength,
+    Agility,
+}
+
 // combat-related properties and methods (monster, player, NPC).
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Fighter {
@@ -308,6 +1094,12 @@ pub enum Ai {
         previous_ai: Box<Ai>,
         num_turns: i32,
     },
+    /// heading for where an alarm went off
+    Alerted {
+        previous_ai: Box<Ai>,
+        x: i32,
+        y: i32,
+    },
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -345,15 +1137,39 @@ impl Messages {
     }
 }
 
+/// what happens when an item is used
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Item {
-    Heal,
-    ScrollLightning,
-    ScrollConfusion,
-    ScrollFireball,
-    Sword,
-    Shield,
+    Heal { amount: i32 },
+    /// strikes the closest monster in range
+    Lightning { damage: i32, range: i32 },
+    Confusion { range: i32, turns: i32 },
+    Fireball { damage: i32, radius: i32 },
+    /// toggles the item's `Equipment`
+    Equipment,
+}
 
+/// a kind of item, as described in `data/items.json`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ItemDef {
+    pub id: String,
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub effect: Item,
+    #[serde(default)]
+    pub equipment: Option<Equipment>,
+    /// relative chance to show up, by dungeon level; empty if it is never found lying around
+    pub spawn: Vec<Transition>,
+}
+
+impl ItemDef {
+    pub fn create(&self, x: i32, y: i32) -> Object {
+        let mut item = Object::new(x, y, self.glyph, &self.name, self.color, false);
+        item.item = Some(self.effect);
+        item.equipment = self.equipment;
+        item
+    }
 }
 #[derive(Serialize, Deserialize)]
 pub enum UseResult {
@@ -362,7 +1178,7 @@ pub enum UseResult {
     Cancelled,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Transition {
     pub level: u32,
     pub value: u32,
@@ -372,6 +1188,7 @@ pub struct Transition {
 /// An object that can be equipped, yielding bonuses.
 pub struct Equipment {
     pub slot: Slot,
+    #[serde(default)]
     pub equipped: bool,
     pub power_bonus: i32,
     pub defense_bonus: i32,